@@ -0,0 +1,244 @@
+//! Messages exchanged between the wasm app and the native host.
+//!
+//! The page sends an [`Envelope`] as JSON through `window.external.invoke`,
+//! the host answers by evaluating `window.__frickBridgeReceive(json)` with a [`HostMessage`].
+
+mod settings;
+
+pub use settings::{ProxyConfig, ProxyStyle, Settings, SETTINGS_VERSION};
+
+use serde::{Deserialize, Serialize};
+
+/// Name of the global function the page registers to receive [`HostMessage`]s.
+pub const RECEIVE_FUNCTION: &str = "__frickBridgeReceive";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Envelope {
+    /// Set when the page expects a [`HostMessage::Reply`] carrying the same id.
+    pub id: Option<u32>,
+    pub request: Request,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Request {
+    /// Performs a GET request without any CORS restrictions.
+    Fetch {
+        url: String,
+    },
+    /// Asks the user for a directory and queues a download of a song into it.
+    ///
+    /// The host resolves `metadata.source_url` again for every attempt, since stream urls expire.
+    Download {
+        /// One of the keys of the song's streams.
+        quality: String,
+        /// The stream's type, which picks the file's extension.
+        mime_type: String,
+        metadata: Metadata,
+    },
+    /// Lists every download the host knows about, including those from previous runs.
+    ListDownloads,
+    PauseDownload {
+        id: u32,
+    },
+    /// Resumes a paused download or retries a failed one.
+    ResumeDownload {
+        id: u32,
+    },
+    CancelDownload {
+        id: u32,
+    },
+    /// Forgets about a download that isn't running anymore.
+    RemoveDownload {
+        id: u32,
+    },
+    /// Sets how many downloads may run at the same time.
+    SetDownloadParallelism {
+        parallelism: usize,
+    },
+    /// Tells the host what's playing, whenever the song or its playback state changes.
+    ReportPlayback {
+        song: Option<Metadata>,
+        status: PlaybackStatus,
+        /// In seconds.
+        position: f64,
+        /// In seconds, 0 while it isn't known.
+        duration: f64,
+    },
+    /// Shows a desktop notification for a song that just started.
+    Notify {
+        song: Metadata,
+    },
+    /// Asks for the window's geometry saved by the previous run, answered with a [`Response::Window`].
+    GetWindow,
+    /// Saves the window's geometry so the next run can restore it.
+    SaveWindow {
+        geometry: WindowGeometry,
+    },
+    /// Asks for the settings in the host's config file, answered with a [`Response::Settings`].
+    GetSettings,
+    /// Answered with a [`Response::Saved`] once they're written to the config file.
+    SaveSettings {
+        settings: Settings,
+    },
+}
+
+/// Where the window is on the screen, in pixels.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WindowGeometry {
+    pub width: i32,
+    pub height: i32,
+    /// Missing until the window has been placed somewhere.
+    pub x: Option<i32>,
+    pub y: Option<i32>,
+    /// The size and position are kept from before the window was maximized.
+    pub maximized: bool,
+}
+
+impl Default for WindowGeometry {
+    fn default() -> Self {
+        Self {
+            width: 1024,
+            height: 720,
+            x: None,
+            y: None,
+            maximized: false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlaybackStatus {
+    Playing,
+    Paused,
+    Stopped,
+}
+
+/// What gets written into the tags of a downloaded file.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Metadata {
+    pub title: String,
+    pub artist: Option<String>,
+    pub source_url: String,
+    pub artwork_url: Option<String>,
+}
+
+/// A job in the host's download queue.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Download {
+    pub id: u32,
+    pub title: String,
+    pub state: DownloadState,
+    /// Bytes for progressive streams, segments for HLS ones.
+    pub downloaded: u64,
+    pub total: Option<u64>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DownloadState {
+    Queued,
+    Running,
+    Paused,
+    /// Failed, and will be queued again after a delay.
+    Retrying {
+        attempt: u32,
+        delay_secs: u64,
+    },
+    Finished {
+        path: String,
+    },
+    Failed {
+        message: String,
+    },
+    Cancelled,
+}
+
+impl DownloadState {
+    /// Whether the download can't make any more progress without being resumed.
+    pub fn is_done(&self) -> bool {
+        matches!(
+            self,
+            DownloadState::Finished { .. }
+                | DownloadState::Failed { .. }
+                | DownloadState::Cancelled
+        )
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Response {
+    Text {
+        body: String,
+    },
+    /// A download was queued, its [`Event`]s carry the same id.
+    Download {
+        id: u32,
+    },
+    Downloads {
+        downloads: Vec<Download>,
+        parallelism: usize,
+    },
+    Window {
+        geometry: WindowGeometry,
+    },
+    Settings {
+        settings: Settings,
+    },
+    Saved,
+    /// The user backed out of a dialog.
+    Cancelled,
+    Error {
+        message: String,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum HostMessage {
+    Reply { id: u32, response: Response },
+    Event { event: Event },
+}
+
+/// Things happening on the host that the page didn't directly ask for.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    /// A download was queued or changed state, or made progress.
+    DownloadUpdated {
+        download: Download,
+    },
+    DownloadRemoved {
+        id: u32,
+    },
+    /// Playback was controlled from outside the window, like from the desktop's media controls.
+    Control {
+        control: Control,
+    },
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Control {
+    Play,
+    Pause,
+    PlayPause,
+    Stop,
+    Next,
+    Previous,
+    /// By an offset in seconds, negative for backwards.
+    Seek {
+        offset: f64,
+    },
+    /// To a position in seconds.
+    SetPosition {
+        position: f64,
+    },
+    /// Loads the song at `url`.
+    Open {
+        url: String,
+    },
+}
@@ -0,0 +1,189 @@
+//! The user's settings, shared by the host, which keeps them in `config.toml`, and the page.
+
+use frick_providers::{Config, QualityPolicy};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Bumped whenever saved settings need [`Settings::migrate`] to be read correctly.
+pub const SETTINGS_VERSION: u32 = 1;
+const DEFAULT_PROXY_URL: &str = "https://warp-co.rs";
+
+/// TOML needs plain values to come before tables, which is why the fields are in this order.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// The [`SETTINGS_VERSION`] the settings were saved with, 0 for ones from before there was one.
+    #[serde(default)]
+    pub version: u32,
+    /// Whether the desktop app notifies about new songs.
+    pub notifications: bool,
+    #[serde(with = "policy_key")]
+    pub quality_policy: QualityPolicy,
+    /// Only used in the browser, the desktop app has a proxy of its own.
+    pub proxy: ProxyConfig,
+    pub providers: Config,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            version: SETTINGS_VERSION,
+            proxy: ProxyConfig::default(),
+            providers: Config::default(),
+            quality_policy: QualityPolicy::default(),
+            notifications: true,
+        }
+    }
+}
+
+impl Settings {
+    /// Brings settings saved by an older version up to date, ones from a newer version are left alone.
+    ///
+    /// Fields added since are filled in with their defaults while deserializing,
+    /// so only ones whose meaning changed need to be handled here.
+    pub fn migrate(mut self) -> Self {
+        // Version 0 is what the settings were before they were versioned, which they're the same as.
+        if self.version < SETTINGS_VERSION {
+            self.version = SETTINGS_VERSION;
+        }
+        self
+    }
+
+    /// Whether the settings were saved by a newer version, which they shouldn't be overwritten by.
+    pub fn is_from_newer_version(&self) -> bool {
+        self.version > SETTINGS_VERSION
+    }
+}
+
+/// Saves a [`QualityPolicy`] as a string like `prefer_opus` or `codec:aac`, since TOML has no enums.
+mod policy_key {
+    use super::*;
+
+    const CODEC_PREFIX: &str = "codec:";
+
+    pub fn serialize<S: Serializer>(
+        policy: &QualityPolicy,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match policy {
+            QualityPolicy::HighestBitrate => serializer.serialize_str("highest_bitrate"),
+            QualityPolicy::PreferOpus => serializer.serialize_str("prefer_opus"),
+            QualityPolicy::DataSaver => serializer.serialize_str("data_saver"),
+            QualityPolicy::Codec(codec) => {
+                serializer.serialize_str(&format!("{}{}", CODEC_PREFIX, codec))
+            }
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<QualityPolicy, D::Error> {
+        let key = String::deserialize(deserializer)?;
+        Ok(match key.as_str() {
+            "highest_bitrate" => QualityPolicy::HighestBitrate,
+            "prefer_opus" => QualityPolicy::PreferOpus,
+            "data_saver" => QualityPolicy::DataSaver,
+            _ => match key.strip_prefix(CODEC_PREFIX) {
+                Some(codec) => QualityPolicy::Codec(codec.to_owned()),
+                None => {
+                    return Err(serde::de::Error::custom(format!(
+                        "unknown quality policy {}",
+                        key
+                    )))
+                }
+            },
+        })
+    }
+}
+
+/// How a proxy expects to be given the upstream url.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProxyStyle {
+    /// `https://warp-co.rs/https://example.com`
+    PathPrefix,
+    /// `https://proxy.example.org/?url=https%3A%2F%2Fexample.com`
+    Query,
+}
+
+impl ProxyStyle {
+    pub const ALL: [ProxyStyle; 2] = [ProxyStyle::PathPrefix, ProxyStyle::Query];
+
+    pub fn key(self) -> &'static str {
+        match self {
+            ProxyStyle::PathPrefix => "path_prefix",
+            ProxyStyle::Query => "query",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|style| style.key() == key)
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ProxyStyle::PathPrefix => "Path prefix (https://proxy/https://site)",
+            ProxyStyle::Query => "Query (https://proxy/?url=https://site)",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProxyConfig {
+    pub url: String,
+    pub style: ProxyStyle,
+}
+
+impl Default for ProxyConfig {
+    fn default() -> Self {
+        Self {
+            url: DEFAULT_PROXY_URL.to_owned(),
+            style: ProxyStyle::PathPrefix,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn migrates_settings_without_a_version() {
+        let settings = toml::from_str::<Settings>("notifications = false\n").unwrap();
+        assert_eq!(settings.version, 0);
+
+        let settings = settings.migrate();
+        assert_eq!(settings.version, SETTINGS_VERSION);
+        assert!(!settings.notifications);
+        assert_eq!(settings.quality_policy, QualityPolicy::default());
+        assert_eq!(settings.proxy, ProxyConfig::default());
+    }
+
+    #[test]
+    fn keeps_codec_policies() {
+        let settings = Settings {
+            quality_policy: QualityPolicy::Codec("aac".to_owned()),
+            ..Settings::default()
+        };
+        let saved = toml::to_string(&settings).unwrap();
+        assert!(saved.contains("quality_policy = \"codec:aac\""));
+        assert_eq!(toml::from_str::<Settings>(&saved).unwrap(), settings);
+    }
+
+    #[test]
+    fn rejects_unknown_policies() {
+        let error = toml::from_str::<Settings>("quality_policy = \"loudest\"\n").unwrap_err();
+        assert!(error.to_string().contains("unknown quality policy loudest"));
+    }
+
+    #[test]
+    fn leaves_settings_from_newer_versions_alone() {
+        let saved = format!(
+            "version = {}\nnotifications = false\n",
+            SETTINGS_VERSION + 1
+        );
+        let settings = toml::from_str::<Settings>(&saved).unwrap().migrate();
+        assert!(settings.is_from_newer_version());
+        assert_eq!(settings.version, SETTINGS_VERSION + 1);
+        assert!(!Settings::default().is_from_newer_version());
+    }
+}
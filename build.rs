@@ -1,23 +1,44 @@
-use color_eyre::eyre::Result;
+use color_eyre::eyre::{eyre, Result};
 use std::env;
 use std::fs;
 use std::path::Path;
 use std::process::Command;
 
+/// Copies `from` into `to` with everything in it, `to` is created if it doesn't exist.
+fn copy_dir(from: &Path, to: &Path) -> Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let destination = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &destination)?;
+        } else {
+            fs::copy(entry.path(), destination)?;
+        }
+    }
+    Ok(())
+}
+
 fn main() -> Result<()> {
     println!("cargo:rerun-if-changed=wasm");
+    println!("cargo:rerun-if-changed=bridge");
+    println!("cargo:rerun-if-changed=providers");
 
     let out_dir = env::var("OUT_DIR")?;
 
     std::env::set_current_dir(Path::new("wasm").canonicalize()?)?;
 
-    Command::new("yarn").arg("build:single-page").status()?;
-    let html_path = Path::new("build").join("index.html").canonicalize()?;
+    // The wasm, js and css stay separate files, so the wasm can be compiled while it's streamed in.
+    let status = Command::new("yarn").arg("build").status()?;
+    if !status.success() {
+        return Err(eyre!("building the page failed with {}", status));
+    }
     let dest_dir = Path::new(&out_dir).join("html");
-    if !dest_dir.exists() {
-        fs::create_dir(&dest_dir)?;
+    // Files from older builds have different hashes in their names, so they'd pile up.
+    if dest_dir.exists() {
+        fs::remove_dir_all(&dest_dir)?;
     }
-    fs::copy(html_path, &dest_dir.join("index.html"))?;
+    copy_dir(&Path::new("dist").canonicalize()?, &dest_dir)?;
 
     println!("cargo:rustc-env=APP_HTML_DIR={}", dest_dir.display());
 
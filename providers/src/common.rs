@@ -0,0 +1,235 @@
+use crate::quality::Quality;
+use async_trait::async_trait;
+use eyre::Result;
+use getset::Getters;
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// How providers reach the web, implemented once for browsers and once for native builds.
+#[async_trait(?Send)]
+pub trait HttpClient {
+    /// Fetches `url` and returns the response body.
+    ///
+    /// `cross_origin` is set for hosts that don't send CORS headers, which browsers can only reach through a proxy.
+    async fn get_text(&self, url: &str, cross_origin: bool) -> Result<String>;
+}
+
+/// Where providers keep what's expensive to look up, like SoundCloud's client id.
+pub trait Storage {
+    fn get(&self, key: &str) -> Option<String>;
+    fn set(&self, key: &str, value: &str) -> Result<()>;
+}
+
+#[async_trait(?Send)]
+pub trait Provider {
+    /// A human readable name, like "SoundCloud".
+    fn name(&self) -> &'static str;
+    fn is_match(&self, url: &str) -> bool;
+    async fn song_from_url(&self, url: &str) -> Result<Song>;
+    /// Finds songs matching `query`, their urls can be passed to [`Provider::song_from_url`].
+    async fn search(&self, _query: &str) -> Result<Vec<SearchResult>> {
+        Ok(Vec::new())
+    }
+    fn is_hls(&self) -> bool;
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct SearchResult {
+    pub title: String,
+    pub artist: Option<String>,
+    pub url: String,
+    pub artwork_url: Option<String>,
+}
+
+#[derive(Clone, Debug, Getters, Serialize)]
+#[getset(get = "pub")]
+pub struct Song {
+    pub(crate) title: String,
+    pub(crate) artist: Option<String>,
+    /// The page the song was resolved from.
+    pub(crate) source_url: String,
+    /// ```text
+    /// {
+    ///     "sq-mp3": [(true, "audio/mpeg", "https://example.com/sq/song.m3u8")],
+    ///     "sq-opus": [(true, "audio/ogg; codecs=\"opus\"", "https://example.com/sq/song.opus.m3u8")],
+    ///     "251": [(false, "audio/webm; codecs=\"opus\"", "https://example.com/song.webm")],
+    ///     // etc...
+    ///     // the boolean means "is hls".
+    /// }
+    /// ```
+    ///
+    /// The keys are opaque, [`Song::qualities`] describes them.
+    pub(crate) urls: HashMap<String, Vec<(bool, String, String)>>,
+    /// What each key in `urls` is, for picking one and showing it.
+    pub(crate) qualities: HashMap<String, Quality>,
+    pub(crate) artwork_url: Option<String>,
+    /// Offset in seconds to start playback at, taken from the pasted url.
+    pub(crate) start_time: Option<f64>,
+    /// When the earliest of the signed stream urls stops working, in seconds since the epoch.
+    ///
+    /// Resolving the song again gets fresh ones.
+    pub(crate) expires_at: Option<f64>,
+}
+
+impl Song {
+    /// Whether the stream urls stop working within `margin` seconds of `now`, in seconds since the epoch.
+    pub fn expires_within(&self, now: f64, margin: f64) -> bool {
+        self.expires_at
+            .map_or(false, |expires_at| expires_at <= now + margin)
+    }
+
+    /// The key `quality` goes by now, also taking keys from before qualities were told apart by codec:
+    /// SoundCloud's bare `sq` and `hq`, and the mime types YouTube's qualities were keyed by.
+    ///
+    /// Old keys that match several qualities get the highest bitrate one.
+    pub fn quality_key(&self, quality: &str) -> Option<String> {
+        if self.urls.contains_key(quality) {
+            return Some(quality.to_owned());
+        }
+        let bitrate = |key: &String| self.qualities.get(key).and_then(|quality| quality.bitrate);
+        let prefix = format!("{}-", quality);
+        self.urls
+            .iter()
+            .filter(|(key, streams)| {
+                key.starts_with(&prefix)
+                    || streams.iter().any(|(_, mime_type, _)| mime_type == quality)
+            })
+            .map(|(key, _)| key)
+            .max_by(|&a, &b| bitrate(a).cmp(&bitrate(b)).then(b.cmp(a)))
+            .cloned()
+    }
+}
+
+/// Finds the earliest expiry among signed urls, like googlevideo's `expire=` or SoundCloud's `expires=`.
+pub(crate) fn expiry(urls: &HashMap<String, Vec<(bool, String, String)>>) -> Option<f64> {
+    lazy_static! {
+        static ref EXPIRE_REGEX: Regex = Regex::new(r"(?i)[?&]expires?=(\d+)").unwrap();
+    }
+
+    urls.values()
+        .flatten()
+        .filter_map(|(_, _, url)| EXPIRE_REGEX.captures(url)?.get(1)?.as_str().parse().ok())
+        .fold(None, |earliest: Option<f64>, expiry| {
+            Some(earliest.map_or(expiry, |earliest| earliest.min(expiry)))
+        })
+}
+
+/// Parses a start offset as found in shared links, e.g. `90`, `90s`, `1m30s`, `1h2m3s`, `1:30` or `1:02:03`.
+pub fn parse_timestamp(timestamp: &str) -> Option<f64> {
+    lazy_static! {
+        static ref UNITS_REGEX: Regex =
+            Regex::new(r"^(?:(\d+)h)?(?:(\d+)m)?(?:(\d+(?:\.\d+)?)s?)?$").unwrap();
+    }
+
+    if timestamp.is_empty() {
+        return None;
+    }
+
+    if timestamp.contains(':') {
+        return timestamp.split(':').try_fold(0.0, |total, part| {
+            part.parse::<f64>().ok().map(|value| total * 60.0 + value)
+        });
+    }
+
+    let captures = UNITS_REGEX.captures(timestamp)?;
+    let unit = |index: usize, multiplier: f64| {
+        captures
+            .get(index)
+            .and_then(|m| m.as_str().parse::<f64>().ok())
+            .map_or(0.0, |value| value * multiplier)
+    };
+    Some(unit(1, 3600.0) + unit(2, 60.0) + unit(3, 1.0))
+}
+
+/// Percent-encodes `value` for use in a query string.
+pub(crate) fn encode_query(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_timestamps() {
+        assert_eq!(parse_timestamp("90"), Some(90.0));
+        assert_eq!(parse_timestamp("90s"), Some(90.0));
+        assert_eq!(parse_timestamp("1.5s"), Some(1.5));
+        assert_eq!(parse_timestamp("1m30s"), Some(90.0));
+        assert_eq!(parse_timestamp("2m"), Some(120.0));
+        assert_eq!(parse_timestamp("1h2m3s"), Some(3723.0));
+        assert_eq!(parse_timestamp("1:30"), Some(90.0));
+        assert_eq!(parse_timestamp("1:02:03"), Some(3723.0));
+        assert_eq!(parse_timestamp(""), None);
+        assert_eq!(parse_timestamp("soon"), None);
+        assert_eq!(parse_timestamp("1:xx"), None);
+        assert_eq!(parse_timestamp("30s1m"), None);
+    }
+
+    #[test]
+    fn finds_the_earliest_expiry() {
+        let mut urls = HashMap::new();
+        urls.insert(
+            "251".to_owned(),
+            vec![(
+                false,
+                "audio/webm".to_owned(),
+                "https://r1.googlevideo.com/videoplayback?expire=1700000200&itag=251".to_owned(),
+            )],
+        );
+        urls.insert(
+            "sq-mp3".to_owned(),
+            vec![
+                (
+                    true,
+                    "audio/mpeg".to_owned(),
+                    "https://cf-hls-media.sndcdn.com/playlist/a.m3u8?Policy=x&Expires=1700000100"
+                        .to_owned(),
+                ),
+                (
+                    true,
+                    "audio/mpeg".to_owned(),
+                    "https://example.com/a.m3u8?notexpires=1&unexpire=5".to_owned(),
+                ),
+            ],
+        );
+        assert_eq!(expiry(&urls), Some(1_700_000_100.0));
+
+        urls.remove("sq-mp3");
+        assert_eq!(expiry(&urls), Some(1_700_000_200.0));
+        urls.clear();
+        assert_eq!(expiry(&urls), None);
+    }
+
+    #[test]
+    fn ignores_parameters_that_only_end_in_expire() {
+        let mut urls = HashMap::new();
+        urls.insert(
+            "a".to_owned(),
+            vec![(
+                false,
+                "audio/mpeg".to_owned(),
+                "https://example.com/song?noexpire=1&expires=abc".to_owned(),
+            )],
+        );
+        assert_eq!(expiry(&urls), None);
+    }
+
+    #[test]
+    fn encodes_query_values() {
+        assert_eq!(encode_query("Az09-_.~"), "Az09-_.~");
+        assert_eq!(encode_query("a b&c=d/e?"), "a%20b%26c%3Dd%2Fe%3F");
+        assert_eq!(encode_query("née"), "n%C3%A9e");
+        assert_eq!(encode_query(""), "");
+    }
+}
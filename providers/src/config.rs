@@ -0,0 +1,38 @@
+//! What the user can change about the providers.
+
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_INVIDIOUS_INSTANCE: &str = "https://invidious.kavin.rocks";
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// The Invidious instance YouTube songs are resolved through.
+    pub invidious_instance: String,
+    /// Names of the providers the user turned off, as returned by [`crate::Provider::name`].
+    ///
+    /// Providers are listed when they're off rather than on, so ones added later start out enabled.
+    pub disabled: Vec<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            invidious_instance: DEFAULT_INVIDIOUS_INSTANCE.to_owned(),
+            disabled: Vec::new(),
+        }
+    }
+}
+
+impl Config {
+    pub fn is_enabled(&self, name: &str) -> bool {
+        !self.disabled.iter().any(|disabled| disabled == name)
+    }
+
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) {
+        self.disabled.retain(|disabled| disabled != name);
+        if !enabled {
+            self.disabled.push(name.to_owned());
+        }
+    }
+}
@@ -0,0 +1,63 @@
+//! Turns links to songs into playable streams, in the browser and natively.
+//!
+//! Providers only talk to the outside world through [`HttpClient`] and [`Storage`],
+//! which the `web` and `native` features implement.
+
+mod common;
+mod config;
+#[cfg(feature = "native")]
+pub mod native;
+mod quality;
+#[cfg(feature = "soundcloud")]
+mod soundcloud;
+#[cfg(feature = "web")]
+pub mod web;
+#[cfg(feature = "youtube")]
+mod youtube;
+
+pub use common::{parse_timestamp, HttpClient, Provider, SearchResult, Song, Storage};
+pub use config::{Config, DEFAULT_INVIDIOUS_INSTANCE};
+pub use quality::{Quality, QualityPolicy};
+#[cfg(feature = "soundcloud")]
+pub use soundcloud::SoundCloud;
+#[cfg(feature = "youtube")]
+pub use youtube::YouTube;
+
+pub use async_trait::async_trait;
+
+use eyre::Result;
+use std::rc::Rc;
+
+/// The names of the providers enabled at compile time, as returned by [`Provider::name`].
+pub fn names() -> Vec<&'static str> {
+    #[allow(unused_mut)]
+    let mut names = Vec::new();
+    #[cfg(feature = "soundcloud")]
+    names.push(soundcloud::NAME);
+    #[cfg(feature = "youtube")]
+    names.push(youtube::NAME);
+    names
+}
+
+/// Sets up every provider enabled at compile time that `config` doesn't turn off.
+#[allow(unused_variables)]
+pub async fn all(
+    http: Rc<dyn HttpClient>,
+    storage: Rc<dyn Storage>,
+    config: &Config,
+) -> Result<Vec<Rc<dyn Provider>>> {
+    #[allow(unused_mut)]
+    let mut providers = Vec::<Rc<dyn Provider>>::new();
+    #[cfg(feature = "soundcloud")]
+    if config.is_enabled(soundcloud::NAME) {
+        providers.push(Rc::new(SoundCloud::new(Rc::clone(&http), storage).await?));
+    }
+    #[cfg(feature = "youtube")]
+    if config.is_enabled(youtube::NAME) {
+        providers.push(Rc::new(YouTube::new(
+            http,
+            config.invidious_instance.clone(),
+        )));
+    }
+    Ok(providers)
+}
@@ -0,0 +1,64 @@
+use super::common::{HttpClient, Storage};
+use async_trait::async_trait;
+use eyre::{eyre, Result};
+use log::*;
+use reqwest::blocking::Client;
+use std::{collections::HashMap, fs, path::PathBuf};
+
+/// Fetches with blocking requests, so it's meant for executors that don't mind being blocked,
+/// like `futures::executor::block_on`.
+#[derive(Default)]
+pub struct ReqwestClient {
+    client: Client,
+}
+
+#[async_trait(?Send)]
+impl HttpClient for ReqwestClient {
+    async fn get_text(&self, url: &str, _cross_origin: bool) -> Result<String> {
+        trace!("fetching {}", url);
+        Ok(self.client.get(url).send()?.error_for_status()?.text()?)
+    }
+}
+
+/// A JSON object in a file, read and written on every access.
+pub struct FileStorage {
+    path: PathBuf,
+}
+
+impl FileStorage {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    /// `frick-dmca/storage.json` in the user's data directory.
+    pub fn open_default() -> Result<Self> {
+        let path = dirs::data_dir()
+            .ok_or_else(|| eyre!("couldn't find a data directory"))?
+            .join("frick-dmca")
+            .join("storage.json");
+        Ok(Self::new(path))
+    }
+
+    fn read(&self) -> HashMap<String, String> {
+        fs::read(&self.path)
+            .ok()
+            .and_then(|json| serde_json::from_slice(&json).ok())
+            .unwrap_or_default()
+    }
+}
+
+impl Storage for FileStorage {
+    fn get(&self, key: &str) -> Option<String> {
+        self.read().remove(key)
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<()> {
+        let mut values = self.read();
+        values.insert(key.to_owned(), value.to_owned());
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&self.path, serde_json::to_vec_pretty(&values)?)?;
+        Ok(())
+    }
+}
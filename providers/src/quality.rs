@@ -0,0 +1,243 @@
+//! Telling a song's qualities apart and picking one of them.
+
+use crate::common::Song;
+use serde::{Deserialize, Serialize};
+
+/// The codec and bitrate of one of a song's qualities.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Quality {
+    /// Lowercase, like "opus", "aac" or "mp3".
+    pub codec: String,
+    /// In kbps, when the provider says or it's well known.
+    pub bitrate: Option<u32>,
+}
+
+impl Quality {
+    /// Takes the codec from a mime type like `audio/webm; codecs="opus"` or `audio/mpeg`.
+    pub fn from_mime_type(mime_type: &str, bitrate: Option<u32>) -> Self {
+        let codecs = mime_type
+            .split(';')
+            .filter_map(|parameter| parameter.trim().strip_prefix("codecs="))
+            .map(|codecs| codecs.trim_matches('"').to_lowercase())
+            .next();
+        let codec = match codecs.as_deref() {
+            Some(codec) if codec.starts_with("mp4a") => "aac".to_owned(),
+            Some(codec) => codec.split(',').next().unwrap_or(codec).to_owned(),
+            None => match mime_type.split(';').next().unwrap_or("").trim() {
+                "audio/mpeg" => "mp3".to_owned(),
+                "audio/mp4" | "audio/aac" => "aac".to_owned(),
+                "audio/ogg" => "vorbis".to_owned(),
+                "audio/webm" => "opus".to_owned(),
+                other => other.trim_start_matches("audio/").to_owned(),
+            },
+        };
+        Self { codec, bitrate }
+    }
+
+    /// Something like "Opus 160 kbps".
+    pub fn label(&self) -> String {
+        let codec = match self.codec.as_str() {
+            "opus" => "Opus".to_owned(),
+            "vorbis" => "Vorbis".to_owned(),
+            codec => codec.to_uppercase(),
+        };
+        match self.bitrate {
+            Some(bitrate) => format!("{} {} kbps", codec, bitrate),
+            None => codec,
+        }
+    }
+}
+
+/// How a quality is picked when the user didn't pick one.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QualityPolicy {
+    HighestBitrate,
+    /// The highest bitrate Opus quality, if there's one.
+    PreferOpus,
+    /// The lowest bitrate quality.
+    DataSaver,
+    /// The highest bitrate quality with this codec, if there's one.
+    Codec(String),
+}
+
+impl Default for QualityPolicy {
+    fn default() -> Self {
+        QualityPolicy::HighestBitrate
+    }
+}
+
+impl QualityPolicy {
+    /// Returns the key of the quality to play, ties go to the lowest key so the same song always gets the same one.
+    pub fn pick(&self, song: &Song) -> Option<String> {
+        let bitrate = |key: &String| {
+            song.qualities()
+                .get(key)
+                .and_then(|quality| quality.bitrate)
+        };
+        let highest = |codec: Option<&str>| {
+            song.urls()
+                .keys()
+                .filter(|&key| {
+                    codec.map_or(true, |codec| {
+                        song.qualities()
+                            .get(key)
+                            .map_or(false, |quality| quality.codec == codec)
+                    })
+                })
+                .max_by(|&a, &b| bitrate(a).cmp(&bitrate(b)).then(b.cmp(a)))
+                .cloned()
+        };
+        match self {
+            QualityPolicy::HighestBitrate => highest(None),
+            QualityPolicy::PreferOpus => highest(Some("opus")).or_else(|| highest(None)),
+            QualityPolicy::DataSaver => song
+                .urls()
+                .keys()
+                // Qualities with an unknown bitrate might be the biggest ones.
+                .min_by_key(|&key| (bitrate(key).unwrap_or(u32::MAX), key))
+                .cloned(),
+            QualityPolicy::Codec(codec) => highest(Some(codec)).or_else(|| highest(None)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// A song with one stream for each of `qualities`, given as key, mime type and bitrate.
+    fn with_qualities(qualities: &[(&str, &str, Option<u32>)]) -> Song {
+        let mut urls = HashMap::new();
+        let mut details = HashMap::new();
+        for &(key, mime_type, bitrate) in qualities {
+            let url = format!("https://example.com/{}", key);
+            urls.insert(key.to_owned(), vec![(false, mime_type.to_owned(), url)]);
+            details.insert(key.to_owned(), Quality::from_mime_type(mime_type, bitrate));
+        }
+        Song {
+            title: "Song".to_owned(),
+            artist: None,
+            source_url: "https://example.com/song".to_owned(),
+            urls,
+            qualities: details,
+            artwork_url: None,
+            start_time: None,
+            expires_at: None,
+        }
+    }
+
+    const OPUS: &str = "audio/webm; codecs=\"opus\"";
+    const AAC: &str = "audio/mp4; codecs=\"mp4a.40.2\"";
+    const MP3: &str = "audio/mpeg";
+
+    #[test]
+    fn reads_codecs_from_mime_types() {
+        let codec = |mime_type| Quality::from_mime_type(mime_type, None).codec;
+        assert_eq!(codec(OPUS), "opus");
+        assert_eq!(codec(AAC), "aac");
+        assert_eq!(codec("audio/mp4; codecs=\"MP4A.40.5\""), "aac");
+        assert_eq!(codec("audio/ogg; codecs=\"opus\""), "opus");
+        assert_eq!(codec("audio/ogg; codecs=\"vorbis,opus\""), "vorbis");
+        assert_eq!(codec(MP3), "mp3");
+        assert_eq!(codec("audio/mp4"), "aac");
+        assert_eq!(codec("audio/ogg"), "vorbis");
+        assert_eq!(codec("audio/flac"), "flac");
+        assert_eq!(
+            Quality::from_mime_type(MP3, Some(128)).label(),
+            "MP3 128 kbps"
+        );
+        assert_eq!(Quality::from_mime_type(OPUS, None).label(), "Opus");
+    }
+
+    #[test]
+    fn picks_the_highest_bitrate() {
+        let song = with_qualities(&[
+            ("140", AAC, Some(128)),
+            ("251", OPUS, Some(160)),
+            ("250", OPUS, None),
+        ]);
+        assert_eq!(
+            QualityPolicy::HighestBitrate.pick(&song).as_deref(),
+            Some("251")
+        );
+    }
+
+    #[test]
+    fn breaks_ties_by_the_lowest_key() {
+        let song = with_qualities(&[
+            ("b", OPUS, Some(128)),
+            ("a", AAC, Some(128)),
+            ("c", MP3, Some(128)),
+        ]);
+        assert_eq!(
+            QualityPolicy::HighestBitrate.pick(&song).as_deref(),
+            Some("a")
+        );
+        assert_eq!(QualityPolicy::DataSaver.pick(&song).as_deref(), Some("a"));
+    }
+
+    #[test]
+    fn saves_data_without_guessing_unknown_bitrates() {
+        let song = with_qualities(&[
+            ("a", OPUS, None),
+            ("b", AAC, Some(128)),
+            ("c", OPUS, Some(48)),
+        ]);
+        assert_eq!(QualityPolicy::DataSaver.pick(&song).as_deref(), Some("c"));
+
+        let unknown = with_qualities(&[("b", OPUS, None), ("a", AAC, None)]);
+        assert_eq!(
+            QualityPolicy::DataSaver.pick(&unknown).as_deref(),
+            Some("a")
+        );
+    }
+
+    #[test]
+    fn prefers_codecs_and_falls_back_without_them() {
+        let song = with_qualities(&[
+            ("140", AAC, Some(128)),
+            ("251", OPUS, Some(160)),
+            ("250", OPUS, Some(70)),
+        ]);
+        let aac = QualityPolicy::Codec("aac".to_owned());
+        let mp3 = QualityPolicy::Codec("mp3".to_owned());
+        assert_eq!(aac.pick(&song).as_deref(), Some("140"));
+        assert_eq!(mp3.pick(&song).as_deref(), Some("251"));
+        assert_eq!(
+            QualityPolicy::PreferOpus.pick(&song).as_deref(),
+            Some("251")
+        );
+
+        let without_opus = with_qualities(&[("140", AAC, Some(128)), ("sq-mp3", MP3, Some(256))]);
+        assert_eq!(
+            QualityPolicy::PreferOpus.pick(&without_opus).as_deref(),
+            Some("sq-mp3")
+        );
+        assert_eq!(
+            QualityPolicy::HighestBitrate.pick(&with_qualities(&[])),
+            None
+        );
+    }
+
+    #[test]
+    fn maps_old_quality_keys() {
+        let soundcloud = with_qualities(&[("sq-mp3", MP3, Some(128)), ("sq-opus", OPUS, Some(64))]);
+        assert_eq!(
+            soundcloud.quality_key("sq-opus").as_deref(),
+            Some("sq-opus")
+        );
+        assert_eq!(soundcloud.quality_key("sq").as_deref(), Some("sq-mp3"));
+        assert_eq!(soundcloud.quality_key("hq"), None);
+
+        let youtube = with_qualities(&[
+            ("140", AAC, Some(128)),
+            ("251", OPUS, Some(160)),
+            ("250", OPUS, Some(70)),
+        ]);
+        assert_eq!(youtube.quality_key(OPUS).as_deref(), Some("251"));
+        assert_eq!(youtube.quality_key(AAC).as_deref(), Some("140"));
+        assert_eq!(youtube.quality_key("audio/ogg"), None);
+    }
+}
@@ -0,0 +1,271 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use super::common::*;
+use super::quality::Quality;
+use async_trait::async_trait;
+use eyre::{eyre, Result};
+use lazy_static::lazy_static;
+use log::*;
+use regex::Regex;
+use serde::Deserialize;
+
+lazy_static! {
+    // These are taken from youtube-dl:
+    // https://github.com/ytdl-org/youtube-dl/blob/71ddc/youtube_dl/extractor/soundcloud.py
+    static ref SRC_REGEX: Regex = Regex::new(r#"<script[^>]+src="([^"]+)""#).unwrap();
+    static ref CLIENT_ID_REGEX: Regex =
+        Regex::new(r#"client_id\s*:\s*"([0-9a-zA-Z]{32})""#).unwrap();
+    static ref START_REGEX: Regex = Regex::new(r"#t=([0-9hms:.]+)").unwrap();
+    static ref PRESET_BITRATE_REGEX: Regex = Regex::new(r"_(\d+)k$").unwrap();
+}
+
+pub(crate) const NAME: &str = "SoundCloud";
+const API_BASE: &str = "https://api-v2.soundcloud.com";
+const STORAGE_KEY: &str = "soundcloud_client_id";
+
+pub struct SoundCloud {
+    http: Rc<dyn HttpClient>,
+    client_id: String,
+}
+
+impl SoundCloud {
+    pub async fn new(http: Rc<dyn HttpClient>, storage: Rc<dyn Storage>) -> Result<Self> {
+        let client_id = match storage.get(STORAGE_KEY) {
+            Some(client_id) => {
+                debug!("using client id from storage");
+                client_id
+            }
+            None => {
+                let client_id = Self::fetch_client_id(&*http).await?;
+                storage.set(STORAGE_KEY, &client_id)?;
+                client_id
+            }
+        };
+        Ok(Self { http, client_id })
+    }
+
+    async fn fetch_script_srcs(http: &dyn HttpClient) -> Result<Vec<String>> {
+        const SOUNDCLOUD_DOT_COM: &str = "https://soundcloud.com";
+
+        trace!("Fetching {}", SOUNDCLOUD_DOT_COM);
+        let html = http.get_text(SOUNDCLOUD_DOT_COM, true).await?;
+
+        debug!("html: {}", html);
+
+        let mut srcs = SRC_REGEX
+            .captures_iter(&html)
+            .filter_map(|capt| capt.get(1).map(|capt| capt.as_str().to_owned()))
+            .collect::<Vec<String>>();
+        srcs.reverse();
+
+        debug!("srcs: {:#?}", srcs);
+
+        Ok(srcs)
+    }
+
+    async fn fetch_client_id(http: &dyn HttpClient) -> Result<String> {
+        debug!("fetching client id from scripts");
+        let srcs = Self::fetch_script_srcs(http).await?;
+        for src in srcs.into_iter() {
+            trace!("Fetching soundcloud src {}", src);
+
+            let script = http.get_text(&src, false).await?;
+
+            if let Some(capt) = CLIENT_ID_REGEX
+                .captures(&script)
+                .and_then(|capt| capt.get(1))
+            {
+                let client_id = capt.as_str().to_owned();
+                info!("client_id: {}", client_id);
+                return Ok(client_id);
+            }
+        }
+
+        Err(eyre!("couldn't fetch client id"))
+    }
+
+    async fn fetch_info_json(&self, url: &'_ impl AsRef<str>) -> Result<String> {
+        let resolve_url = format!(
+            "{}/resolve?url={}&client_id={}",
+            API_BASE,
+            url.as_ref(),
+            self.client_id
+        );
+        trace!("fetching info json");
+        let resource_url = self.http.get_text(&resolve_url, true).await?;
+        debug!("resource_url: {}", resource_url);
+        Ok(resource_url)
+    }
+}
+
+#[async_trait(?Send)]
+impl Provider for SoundCloud {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn is_match(&self, url: &str) -> bool {
+        lazy_static! {
+            static ref URL_REGEX: Regex =
+                Regex::new(r"^(?:https?://)?(?:www\.|m\.)?soundcloud\.com").unwrap();
+        }
+
+        URL_REGEX.is_match(url)
+    }
+
+    async fn song_from_url(&self, url: &str) -> Result<Song> {
+        #[derive(Debug, Deserialize)]
+        struct Format {
+            pub(crate) protocol: String,
+            pub(crate) mime_type: String,
+        }
+        #[derive(Debug, Deserialize)]
+        struct Transcoding {
+            pub(crate) url: String,
+            pub(crate) quality: String,
+            /// Like "mp3_0_0" or "aac_160k".
+            #[serde(default)]
+            pub(crate) preset: String,
+            pub(crate) format: Format,
+        }
+        #[derive(Debug, Deserialize)]
+        struct Media {
+            pub(crate) transcodings: Vec<Transcoding>,
+        }
+        #[derive(Debug, Deserialize)]
+        struct User {
+            pub(crate) username: String,
+        }
+        #[derive(Debug, Deserialize)]
+        struct SongJson {
+            pub(crate) artwork_url: Option<String>,
+            pub(crate) title: String,
+            /// The track's own url, which mobile and shared links resolve to.
+            pub(crate) permalink_url: String,
+            pub(crate) user: Option<User>,
+            pub(crate) media: Media,
+        }
+        #[derive(Debug, Deserialize)]
+        struct DirectUrlJson {
+            pub(crate) url: String,
+        }
+
+        // The fragment only matters to us, the resolve endpoint doesn't know about it.
+        let (url, start_time) = match url.find('#') {
+            Some(index) => (
+                &url[..index],
+                START_REGEX
+                    .captures(&url[index..])
+                    .and_then(|capt| capt.get(1))
+                    .and_then(|m| parse_timestamp(m.as_str())),
+            ),
+            None => (url, None),
+        };
+
+        let json = self
+            .fetch_info_json(&url)
+            .await
+            .map_err(|_| eyre!("failed to fetch info json"))?;
+
+        let mut serialized = serde_json::from_str::<SongJson>(&json)?;
+        serialized
+            .media
+            .transcodings
+            .retain(|transcoding| &transcoding.format.protocol == "hls");
+        trace!("serialized: {:#?}", serialized);
+        let mut urls = HashMap::<String, Vec<(bool, String, String)>>::new();
+        let mut qualities = HashMap::new();
+        for Transcoding {
+            url,
+            quality,
+            preset,
+            format,
+        } in serialized.media.transcodings
+        {
+            trace!("fetching direct url for {}", url);
+            let direct_url = serde_json::from_str::<DirectUrlJson>(
+                &self
+                    .http
+                    .get_text(&format!("{}?client_id={}", url, self.client_id), true)
+                    .await?,
+            )?;
+            let direct_url = direct_url.url;
+            debug!("direct url: {}", direct_url);
+            let mut details = Quality::from_mime_type(&format.mime_type, None);
+            details.bitrate = bitrate(&preset, &details.codec);
+            // Every codec gets its own key, so one can be preferred over the other.
+            let key = format!("{}-{}", quality, details.codec);
+            let stream = (true, format.mime_type, direct_url);
+            urls.entry(key.clone())
+                .or_insert_with(Vec::new)
+                .push(stream);
+            qualities.insert(key, details);
+        }
+        debug!("urls: {:#?}", urls);
+
+        let expires_at = expiry(&urls);
+        Ok(Song {
+            title: serialized.title,
+            artist: serialized.user.map(|user| user.username),
+            source_url: serialized.permalink_url,
+            urls,
+            qualities,
+            artwork_url: serialized.artwork_url,
+            start_time,
+            expires_at,
+        })
+    }
+
+    async fn search(&self, query: &str) -> Result<Vec<SearchResult>> {
+        #[derive(Debug, Deserialize)]
+        struct User {
+            pub(crate) username: String,
+        }
+        #[derive(Debug, Deserialize)]
+        struct Track {
+            pub(crate) title: String,
+            pub(crate) permalink_url: String,
+            pub(crate) artwork_url: Option<String>,
+            pub(crate) user: Option<User>,
+        }
+        #[derive(Debug, Deserialize)]
+        struct SearchJson {
+            pub(crate) collection: Vec<Track>,
+        }
+
+        let search_url = format!(
+            "{}/search/tracks?q={}&client_id={}&limit=20",
+            API_BASE,
+            encode_query(query),
+            self.client_id
+        );
+        let json = self.http.get_text(&search_url, true).await?;
+        let tracks = serde_json::from_str::<SearchJson>(&json)?.collection;
+        Ok(tracks
+            .into_iter()
+            .map(|track| SearchResult {
+                title: track.title,
+                artist: track.user.map(|user| user.username),
+                url: track.permalink_url,
+                artwork_url: track.artwork_url,
+            })
+            .collect())
+    }
+
+    fn is_hls(&self) -> bool {
+        true
+    }
+}
+
+/// SoundCloud doesn't list bitrates, but some presets are named after theirs and the others are well known.
+fn bitrate(preset: &str, codec: &str) -> Option<u32> {
+    PRESET_BITRATE_REGEX
+        .captures(preset)
+        .and_then(|captures| captures[1].parse().ok())
+        .or(match codec {
+            "mp3" => Some(128),
+            "opus" => Some(64),
+            _ => None,
+        })
+}
@@ -0,0 +1,52 @@
+use super::common::{HttpClient, Storage};
+use async_trait::async_trait;
+use eyre::{eyre, Result};
+use log::*;
+use seed::{browser::web_storage::LocalStorage, prelude::*};
+
+/// Fetches with the browser, sending cross-origin requests through a CORS proxy.
+pub struct FetchClient {
+    proxy: Box<dyn Fn(&str) -> String>,
+}
+
+impl FetchClient {
+    /// `proxy` rewrites a url so it goes through the proxy.
+    pub fn new(proxy: impl Fn(&str) -> String + 'static) -> Self {
+        Self {
+            proxy: Box::new(proxy),
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl HttpClient for FetchClient {
+    async fn get_text(&self, url: &str, cross_origin: bool) -> Result<String> {
+        let request_url = if cross_origin {
+            (self.proxy)(url)
+        } else {
+            url.to_owned()
+        };
+        trace!("fetching {}", request_url);
+        fetch(&request_url)
+            .await
+            .map_err(|_| eyre!("failed to fetch {}", url))?
+            .check_status()
+            .map_err(|_| eyre!("response status isn't ok for {}", url))?
+            .text()
+            .await
+            .map_err(|_| eyre!("failed getting text of {}", url))
+    }
+}
+
+/// The browser's local storage.
+pub struct WebStorage;
+
+impl Storage for WebStorage {
+    fn get(&self, key: &str) -> Option<String> {
+        LocalStorage::get(key).ok()
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<()> {
+        LocalStorage::insert(key, value).map_err(|_| eyre!("failed to store {}", key))
+    }
+}
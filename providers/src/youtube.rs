@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use super::common::*;
+use super::quality::Quality;
+use async_trait::async_trait;
+use eyre::{eyre, Result};
+use lazy_static::lazy_static;
+use log::*;
+use regex::Regex;
+use serde::Deserialize;
+
+pub(crate) const NAME: &str = "YouTube";
+
+pub struct YouTube {
+    http: Rc<dyn HttpClient>,
+    /// Like `https://invidious.kavin.rocks`.
+    instance: String,
+}
+
+lazy_static! {
+    static ref URL_REGEX: Regex =
+        Regex::new(
+            r"^(?:https?://)?(?:www\.|m\.)?(?:youtube\.com/watch\?v=|youtu\.be/|youtube\.com/embed/)([A-Za-z0-9_-]{11})"
+        )
+        .unwrap();
+    static ref START_REGEX: Regex = Regex::new(r"[?&#](?:t|start)=([0-9hms:.]+)").unwrap();
+}
+
+impl YouTube {
+    pub fn new(http: Rc<dyn HttpClient>, instance: String) -> Self {
+        Self {
+            http,
+            instance: instance.trim_end_matches('/').to_owned(),
+        }
+    }
+    async fn fetch_info_json(&self, url: &'_ impl AsRef<str>) -> Result<String> {
+        let url = url.as_ref();
+        // This is guranteed to succeed because we check if the url matches the pattern before even calling this.
+        let video_id = URL_REGEX.captures(&url).unwrap().get(1).map(|m| m.as_str()).unwrap();
+        let api_url = format!(
+            "{}/api/v1/videos/{}?fields=adaptiveFormats,author,title,videoThumbnails",
+            self.instance, video_id
+        );
+        trace!("fetching api response from {}", api_url);
+        self.http.get_text(&api_url, false).await
+    }
+}
+
+#[async_trait(?Send)]
+impl Provider for YouTube {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn is_match(&self, url: &str) -> bool {
+        URL_REGEX.is_match(url)
+    }
+
+    async fn song_from_url(&self, url: &str) -> Result<Song> {
+        #[derive(Debug, Deserialize)]
+        struct VideoThumbnail {
+            pub quality: String,
+            pub url: String,
+        }
+
+        #[derive(Debug, Deserialize)]
+        struct AdaptiveFormatJson {
+            pub url: String,
+            pub itag: String,
+            #[serde(rename = "type")]
+            pub mime_type: String,
+            /// In bits per second, as a string.
+            pub bitrate: Option<String>,
+        }
+
+        #[derive(Debug, Deserialize)]
+        struct SongJson {
+            pub title: String,
+            pub author: Option<String>,
+            #[serde(rename = "adaptiveFormats")]
+            pub adaptive_formats: Vec<AdaptiveFormatJson>,
+            #[serde(rename = "videoThumbnails")]
+            pub video_thumbnails: Vec<VideoThumbnail>,
+        }
+
+        let json = self
+            .fetch_info_json(&url)
+            .await
+            .map_err(|_| eyre!("failed to fetch info json"))?;
+
+        let serialized = serde_json::from_str::<SongJson>(&json)?;
+
+        trace!("serialized: {:#?}", serialized);
+
+        let mut urls = HashMap::<String, Vec<(bool, String, String)>>::new();
+        let mut qualities = HashMap::new();
+        for AdaptiveFormatJson {
+            url,
+            itag,
+            mime_type,
+            bitrate,
+        } in serialized.adaptive_formats
+        {
+            if mime_type.starts_with("audio") {
+                let bitrate = bitrate
+                    .and_then(|bitrate| bitrate.parse::<u32>().ok())
+                    .map(|bitrate| (bitrate + 500) / 1000);
+                qualities.insert(itag.clone(), Quality::from_mime_type(&mime_type, bitrate));
+                urls.entry(itag)
+                    .or_insert_with(Vec::new)
+                    .push((false, mime_type, url));
+            }
+        }
+        debug!("urls: {:#?}", urls);
+
+        let artwork_url = serialized
+            .video_thumbnails
+            .iter()
+            .find_map(|thumb| (thumb.quality == "high").then(|| thumb.url.clone()));
+
+        let start_time = START_REGEX
+            .captures(url)
+            .and_then(|capt| capt.get(1))
+            .and_then(|m| parse_timestamp(m.as_str()));
+
+        // The same video has plenty of urls, history and playlists tell songs apart by this one.
+        let source_url = URL_REGEX
+            .captures(url)
+            .and_then(|capt| capt.get(1))
+            .map_or_else(
+                || url.to_owned(),
+                |m| format!("https://www.youtube.com/watch?v={}", m.as_str()),
+            );
+
+        let expires_at = expiry(&urls);
+        Ok(Song {
+            title: serialized.title,
+            artist: serialized.author,
+            source_url,
+            urls,
+            qualities,
+            artwork_url,
+            start_time,
+            expires_at,
+        })
+    }
+
+    async fn search(&self, query: &str) -> Result<Vec<SearchResult>> {
+        #[derive(Debug, Deserialize)]
+        struct VideoThumbnail {
+            pub quality: String,
+            pub url: String,
+        }
+
+        #[derive(Debug, Deserialize)]
+        struct VideoJson {
+            pub title: String,
+            pub author: Option<String>,
+            #[serde(rename = "videoId")]
+            pub video_id: String,
+            #[serde(rename = "videoThumbnails")]
+            pub video_thumbnails: Vec<VideoThumbnail>,
+        }
+
+        let search_url = format!(
+            "{}/api/v1/search?q={}&type=video&fields=title,author,videoId,videoThumbnails",
+            self.instance,
+            encode_query(query)
+        );
+        trace!("searching {}", search_url);
+        let json = self.http.get_text(&search_url, false).await?;
+        let videos = serde_json::from_str::<Vec<VideoJson>>(&json)?;
+        Ok(videos
+            .into_iter()
+            .map(|video| SearchResult {
+                artwork_url: video
+                    .video_thumbnails
+                    .iter()
+                    .find_map(|thumb| (thumb.quality == "high").then(|| thumb.url.clone())),
+                title: video.title,
+                artist: video.author,
+                url: format!("https://www.youtube.com/watch?v={}", video.video_id),
+            })
+            .collect())
+    }
+
+    fn is_hls(&self) -> bool {
+        true
+    }
+}
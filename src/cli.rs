@@ -0,0 +1,97 @@
+//! Subcommands that resolve songs without showing the app.
+//!
+//! Songs are resolved by the same providers the app uses, so the results match what it would show.
+
+use crate::{download, hls, remux, resolve, settings};
+use bridge::Metadata;
+use color_eyre::eyre::{eyre, Result};
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+#[derive(StructOpt)]
+#[structopt(about = "Listen to and download songs from SoundCloud and YouTube")]
+pub struct Options {
+    #[structopt(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(StructOpt)]
+pub enum Command {
+    /// Prints a song's details and streams as JSON
+    Info { url: String },
+    /// Downloads a song
+    Get {
+        url: String,
+        /// One of the qualities listed by `info`, picked by the quality preference in the settings by default
+        #[structopt(short, long)]
+        quality: Option<String>,
+        /// Where to save the song, its extension may change to match the stream's codec
+        #[structopt(short, long, parse(from_os_str))]
+        output: Option<PathBuf>,
+    },
+    /// Opens the app with a song loaded, or loads it in the app that's already open
+    Play { url: String },
+    /// Opens a `frick://play?url=<url>` link, a link to a song or a playlist's first song, like the desktop entry does
+    Open { target: String },
+    /// Registers the app as the handler for `frick://` links with the desktop
+    InstallDesktopEntry {
+        /// Also open M3U and PLS playlists with the app
+        #[structopt(long)]
+        playlists: bool,
+    },
+}
+
+pub fn info(url: &str) -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(&resolve::song(url)?)?);
+    Ok(())
+}
+
+pub fn get(url: &str, quality: Option<String>, output: Option<PathBuf>) -> Result<()> {
+    let song = resolve::song(url)?;
+    let streams = quality
+        .or_else(|| settings::load().quality_policy.pick(&song))
+        .and_then(|quality| song.quality_key(&quality))
+        .and_then(|quality| song.urls().get(&quality))
+        .ok_or_else(|| {
+            eyre!(
+                "{} has no such quality, pick one of: {}",
+                song.title(),
+                song.urls()
+                    .keys()
+                    .map(|key| match song.qualities().get(key) {
+                        Some(quality) => format!("{} ({})", key, quality.label()),
+                        None => key.clone(),
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        })?;
+    let (is_hls, mime_type, stream_url) = streams
+        .first()
+        .ok_or_else(|| eyre!("{} has no streams", song.title()))?;
+    let path =
+        output.unwrap_or_else(|| PathBuf::from(download::file_name(song.title(), mime_type)));
+    let metadata = Metadata {
+        title: song.title().clone(),
+        artist: song.artist().clone(),
+        source_url: song.source_url().clone(),
+        artwork_url: song.artwork_url().clone(),
+    };
+
+    let progress = |downloaded, total| {
+        match total {
+            Some(total) if total > 0 => eprint!("\r{}%", downloaded * 100 / total),
+            _ => eprint!("\r{} bytes", downloaded),
+        }
+        true
+    };
+    let path = if *is_hls {
+        hls::download(stream_url, &path, progress)
+    } else {
+        download::download(stream_url, &path, progress)
+    }
+    .and_then(|path| remux::finish(&path, mime_type, &metadata))?;
+    eprintln!();
+    println!("{}", path.display());
+    Ok(())
+}
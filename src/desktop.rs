@@ -0,0 +1,238 @@
+//! Registering the app with the desktop, so links in other apps open it.
+//!
+//! The desktop entry runs `frick-dmca open %u`, which takes `frick://play?url=<url>` links,
+//! links to songs and, when installed with `--playlists`, M3U and PLS files.
+
+use color_eyre::eyre::{eyre, Result};
+use reqwest::Url;
+use std::{env, ffi::OsStr, fs, path::Path, process};
+
+const FILE_NAME: &str = "frick-dmca.desktop";
+const SCHEME: &str = "frick";
+const SCHEME_MIME_TYPE: &str = "x-scheme-handler/frick";
+const PLAYLIST_MIME_TYPES: &[&str] = &[
+    "audio/x-mpegurl",
+    "audio/mpegurl",
+    "application/vnd.apple.mpegurl",
+    "audio/x-scpls",
+];
+
+/// Quotes an `Exec` argument as the desktop entry spec asks for.
+fn quote(argument: &str) -> String {
+    let mut quoted = String::from('"');
+    for c in argument.chars() {
+        if matches!(c, '"' | '`' | '$' | '\\') {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Writes the desktop entry to `~/.local/share/applications` and makes it the default for the types it handles.
+pub fn install(playlists: bool) -> Result<()> {
+    let directory = dirs::data_dir()
+        .ok_or_else(|| eyre!("couldn't find a data directory"))?
+        .join("applications");
+    fs::create_dir_all(&directory)?;
+
+    let mut mime_types = vec![SCHEME_MIME_TYPE];
+    if playlists {
+        mime_types.extend_from_slice(PLAYLIST_MIME_TYPES);
+    }
+    let executable = env::current_exe()?;
+    let entry = format!(
+        "[Desktop Entry]\n\
+         Type=Application\n\
+         Name=frick-dmca\n\
+         Comment=Listen to and download songs from SoundCloud and YouTube\n\
+         Exec={} open %u\n\
+         Terminal=false\n\
+         Categories=AudioVideo;Audio;Player;\n\
+         MimeType={};\n",
+        quote(&executable.to_string_lossy()),
+        mime_types.join(";")
+    );
+    let path = directory.join(FILE_NAME);
+    fs::write(&path, entry)?;
+    println!("wrote {}", path.display());
+
+    // Both tools come with desktop-file-utils and xdg-utils, which not every system has.
+    run("update-desktop-database", &[&directory]);
+    let mut arguments = vec!["default", FILE_NAME];
+    arguments.extend(mime_types);
+    run("xdg-mime", &arguments);
+    Ok(())
+}
+
+fn run(program: &str, arguments: &[impl AsRef<OsStr>]) {
+    match process::Command::new(program).args(arguments).status() {
+        Ok(status) if status.success() => {}
+        Ok(status) => eprintln!("{} failed with {}", program, status),
+        Err(e) => eprintln!("couldn't run {}: {}", program, e),
+    }
+}
+
+/// Whether `link` is an http(s) link, the only kind songs can be played from.
+fn is_web_link(link: &str) -> bool {
+    Url::parse(link).map_or(false, |url| matches!(url.scheme(), "http" | "https"))
+}
+
+/// The song a desktop entry was opened with, from a `frick://play?url=` link, a link or a playlist's first entry.
+pub fn song_url(target: &str) -> Result<String> {
+    match Url::parse(target) {
+        Ok(url) if url.scheme() == SCHEME => {
+            if url.host_str() != Some("play") {
+                return Err(eyre!("unknown command in {}", target));
+            }
+            let song = url
+                .query_pairs()
+                .find(|(key, _)| key == "url")
+                .map(|(_, url)| url.into_owned())
+                .ok_or_else(|| eyre!("{} doesn't say what to play", target))?;
+            if !is_web_link(&song) {
+                return Err(eyre!("{} isn't a link to a song", song));
+            }
+            Ok(song)
+        }
+        Ok(url) if url.scheme() == "file" => {
+            let path = url
+                .to_file_path()
+                .map_err(|()| eyre!("{} isn't a local file", target))?;
+            first_entry(&path)
+        }
+        Ok(_) if is_web_link(target) => Ok(target.to_owned()),
+        Ok(url) => Err(eyre!("can't open {} links", url.scheme())),
+        // Not a url, so a path.
+        Err(_) => first_entry(Path::new(target)),
+    }
+}
+
+/// The first song in an M3U or PLS playlist, which has to be a link.
+fn first_entry(path: &Path) -> Result<String> {
+    let text = fs::read_to_string(path)?;
+    let is_pls = path
+        .extension()
+        .map_or(false, |extension| extension.eq_ignore_ascii_case("pls"));
+    let entry = if is_pls {
+        // `File1=https://…`, entries are numbered from 1 and the first one comes first.
+        text.lines()
+            .map(str::trim)
+            .filter(|line| line.starts_with("File"))
+            .find_map(|line| line.splitn(2, '=').nth(1))
+    } else {
+        text.lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty() && !line.starts_with('#'))
+    };
+    let entry = entry
+        .map(str::trim)
+        .ok_or_else(|| eyre!("{} has no songs in it", path.display()))?;
+    if is_web_link(entry) {
+        return Ok(entry.to_owned());
+    }
+    if Url::parse(entry).is_ok() {
+        return Err(eyre!(
+            "{} starts with {}, only links to songs can be played",
+            path.display(),
+            entry
+        ));
+    }
+    // Paths are relative to the playlist, but only links can be played.
+    let entry_path = path.parent().unwrap_or_else(|| Path::new("")).join(entry);
+    Err(eyre!(
+        "{} starts with the local file {}, only links to songs can be played",
+        path.display(),
+        entry_path.display()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes `contents` to a playlist called `name` in its own directory, returning its path.
+    fn playlist(name: &str, contents: &str) -> std::path::PathBuf {
+        let directory =
+            env::temp_dir().join(format!("frick-dmca-desktop-{}", rand::random::<u32>()));
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join(name);
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn remove(path: &Path) {
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn opens_frick_links() {
+        assert_eq!(
+            song_url("frick://play?url=https%3A%2F%2Fsoundcloud.com%2Fa%2Fb").unwrap(),
+            "https://soundcloud.com/a/b"
+        );
+        assert!(song_url("frick://play?url=file%3A%2F%2F%2Fetc%2Fpasswd").is_err());
+        assert!(song_url("frick://play?url=javascript%3Aalert(1)").is_err());
+        assert!(song_url("frick://play").is_err());
+        assert!(song_url("frick://delete?url=https%3A%2F%2Fsoundcloud.com%2Fa%2Fb").is_err());
+    }
+
+    #[test]
+    fn opens_links() {
+        let link = "https://www.youtube.com/watch?v=dQw4w9WgXcQ";
+        assert_eq!(song_url(link).unwrap(), link);
+        assert!(song_url("ftp://example.com/song.mp3").is_err());
+    }
+
+    #[test]
+    fn opens_file_urls() {
+        let path = playlist("list.m3u", "https://soundcloud.com/a/b\n");
+        let url = Url::from_file_path(&path).unwrap();
+        let song = song_url(url.as_str());
+        remove(&path);
+
+        assert_eq!(song.unwrap(), "https://soundcloud.com/a/b");
+    }
+
+    #[test]
+    fn opens_pls_playlists() {
+        let path = playlist(
+            "list.pls",
+            "[playlist]\nNumberOfEntries=2\nFile1=https://soundcloud.com/a/b\nTitle1=B\n\
+             File2=https://soundcloud.com/a/c\nVersion=2\n",
+        );
+        let song = song_url(&path.to_string_lossy());
+        remove(&path);
+
+        assert_eq!(song.unwrap(), "https://soundcloud.com/a/b");
+    }
+
+    #[test]
+    fn opens_m3u_playlists() {
+        let path = playlist(
+            "list.m3u8",
+            "#EXTM3U\n\n#EXTINF:123,A - B\n  https://soundcloud.com/a/b  \nhttps://soundcloud.com/a/c\n",
+        );
+        let song = song_url(&path.to_string_lossy());
+        remove(&path);
+
+        assert_eq!(song.unwrap(), "https://soundcloud.com/a/b");
+    }
+
+    #[test]
+    fn rejects_playlists_starting_with_local_files() {
+        let relative = playlist("list.m3u", "#EXTM3U\nmusic/song.mp3\n");
+        let file_url = playlist("list.m3u", "file:///home/user/song.mp3\n");
+        let relative_error = song_url(&relative.to_string_lossy()).unwrap_err();
+        let file_url_error = song_url(&file_url.to_string_lossy());
+        remove(&relative);
+        remove(&file_url);
+
+        let entry = relative.parent().unwrap().join("music/song.mp3");
+        assert!(relative_error
+            .to_string()
+            .contains(&entry.display().to_string()));
+        assert!(file_url_error.is_err());
+    }
+}
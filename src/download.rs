@@ -0,0 +1,142 @@
+use color_eyre::eyre::{eyre, Result};
+use reqwest::{blocking::Client, header, StatusCode};
+use std::{
+    fs::{self, OpenOptions},
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+/// How often progress is reported at most.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Turns a song title into something every filesystem accepts as a file name.
+pub fn file_name(title: &str, mime_type: &str) -> String {
+    let stem = title
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect::<String>();
+    let stem = stem.trim().trim_end_matches('.');
+    let stem = if stem.is_empty() { "download" } else { stem };
+    format!("{}.{}", stem, extension(mime_type))
+}
+
+/// Picks a file extension for a MIME type such as `audio/webm; codecs="opus"`.
+pub fn extension(mime_type: &str) -> &'static str {
+    let essence = mime_type.split(';').next().unwrap_or_default().trim();
+    match essence {
+        "audio/mp4" => "m4a",
+        "audio/mpeg" => "mp3",
+        "audio/ogg" => "ogg",
+        "audio/webm" => "webm",
+        "audio/aac" => "aac",
+        _ => "bin",
+    }
+}
+
+/// `path`, or the first of `name (2).ext`, `name (3).ext` and so on that's free.
+///
+/// Paths that already exist or that `taken` returns `true` for aren't free.
+pub fn unique_path(path: &Path, taken: impl Fn(&Path) -> bool) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy());
+    (1..)
+        .map(|n| match (n, &extension) {
+            (1, _) => path.to_owned(),
+            (n, Some(extension)) => path.with_file_name(format!("{} ({}).{}", stem, n, extension)),
+            (n, None) => path.with_file_name(format!("{} ({})", stem, n)),
+        })
+        .find(|candidate| !candidate.exists() && !taken(candidate))
+        .unwrap()
+}
+
+/// Where a download goes until it's complete.
+pub fn part_path(path: &Path) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_owned();
+    file_name.push(".part");
+    path.with_file_name(file_name)
+}
+
+/// Writes `contents` next to `path` first and then moves it there,
+/// so a crash halfway through leaves the previous contents instead of a cut off file.
+pub fn write_atomically(path: &Path, contents: impl AsRef<[u8]>) -> Result<()> {
+    let part_path = part_path(path);
+    fs::write(&part_path, contents)?;
+    fs::rename(&part_path, path)?;
+    Ok(())
+}
+
+/// Downloads a progressive stream to `path`, resuming from a previous partial download if there is one.
+///
+/// Returns where it ended up, which is next to `path` if something else got there first.
+/// `progress` is called with the number of bytes downloaded so far and the total size, if known,
+/// and stops the download by returning `false`. The partial download is kept so it can be resumed.
+pub fn download(
+    url: &str,
+    path: &Path,
+    mut progress: impl FnMut(u64, Option<u64>) -> bool,
+) -> Result<PathBuf> {
+    let part_path = part_path(path);
+    let resume_from = fs::metadata(&part_path).map_or(0, |metadata| metadata.len());
+
+    let client = Client::builder().timeout(None).build()?;
+    let mut request = client.get(url);
+    if resume_from > 0 {
+        request = request.header(header::RANGE, format!("bytes={}-", resume_from));
+    }
+    let mut response = request.send()?.error_for_status()?;
+
+    // Servers that ignore the range send the whole file again.
+    let resuming = response.status() == StatusCode::PARTIAL_CONTENT;
+    let mut downloaded = if resuming { resume_from } else { 0 };
+    let total = response.content_length().map(|length| length + downloaded);
+    let mut file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(resuming)
+        .truncate(!resuming)
+        .open(&part_path)?;
+
+    let mut buffer = vec![0; 64 * 1024];
+    let mut last_report = Instant::now();
+    if !progress(downloaded, total) {
+        return Err(eyre!("download interrupted"));
+    }
+    loop {
+        let read = response.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        file.write_all(&buffer[..read])?;
+        downloaded += read as u64;
+        if last_report.elapsed() >= PROGRESS_INTERVAL {
+            if !progress(downloaded, total) {
+                file.flush()?;
+                return Err(eyre!("download interrupted"));
+            }
+            last_report = Instant::now();
+        }
+    }
+    file.flush()?;
+    progress(downloaded, total);
+
+    if let Some(total) = total {
+        if downloaded < total {
+            return Err(eyre!(
+                "connection closed after {} of {} bytes",
+                downloaded,
+                total
+            ));
+        }
+    }
+
+    let path = unique_path(path, |_| false);
+    fs::rename(&part_path, &path)?;
+    Ok(path)
+}
@@ -0,0 +1,622 @@
+//! Downloads HLS streams into a single file.
+//!
+//! Only what's needed to save audio is supported: master and media playlists,
+//! byte ranges, fMP4 init sections and AES-128 encryption.
+
+use crate::download;
+use aes::Aes128;
+use block_modes::{block_padding::Pkcs7, BlockMode, Cbc};
+use color_eyre::eyre::{eyre, Result, WrapErr};
+use reqwest::{blocking::Client, header, Url};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::{self, File, OpenOptions},
+    io::{Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+type Aes128Cbc = Cbc<Aes128, Pkcs7>;
+
+/// How many segments are fetched at the same time.
+const CONCURRENCY: usize = 4;
+/// How many times a segment is retried before giving up on the whole download.
+const RETRIES: u32 = 3;
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+
+#[derive(Clone, Debug, PartialEq)]
+struct ByteRange {
+    length: u64,
+    offset: u64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Key {
+    None,
+    Aes128 { uri: Url, iv: Option<[u8; 16]> },
+}
+
+#[derive(Clone, Debug)]
+struct Segment {
+    uri: Url,
+    byte_range: Option<ByteRange>,
+    key: Key,
+    sequence: u64,
+}
+
+#[derive(Debug, Default)]
+struct MediaPlaylist {
+    /// The fMP4 init section from `#EXT-X-MAP`, which goes before every other segment.
+    map: Option<(Url, Option<ByteRange>)>,
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug)]
+struct Variant {
+    uri: Url,
+    bandwidth: u64,
+    audio_group: Option<String>,
+}
+
+#[derive(Debug)]
+struct Rendition {
+    uri: Url,
+    group_id: String,
+    is_default: bool,
+}
+
+#[derive(Debug)]
+enum Playlist {
+    Master {
+        variants: Vec<Variant>,
+        renditions: Vec<Rendition>,
+    },
+    Media(MediaPlaylist),
+}
+
+/// Parses an attribute list such as `BANDWIDTH=128000,CODECS="mp4a.40.2,opus"`.
+fn parse_attributes(list: &str) -> HashMap<String, String> {
+    let mut attributes = HashMap::new();
+    let mut rest = list;
+    while let Some(eq) = rest.find('=') {
+        let name = rest[..eq].trim().to_owned();
+        rest = &rest[eq + 1..];
+        let value = if let Some(quoted) = rest.strip_prefix('"') {
+            let end = quoted.find('"').unwrap_or(quoted.len());
+            let value = quoted[..end].to_owned();
+            rest = quoted.get(end + 1..).unwrap_or_default();
+            value
+        } else {
+            let end = rest.find(',').unwrap_or(rest.len());
+            let value = rest[..end].trim().to_owned();
+            rest = &rest[end..];
+            value
+        };
+        rest = rest.trim_start_matches(',');
+        attributes.insert(name, value);
+    }
+    attributes
+}
+
+/// Parses `<length>[@<offset>]`, where a missing offset continues from the end of the previous range.
+fn parse_byte_range(value: &str, previous_end: u64) -> Result<ByteRange> {
+    let mut parts = value.splitn(2, '@');
+    let length = parts.next().unwrap_or_default().trim().parse()?;
+    let offset = match parts.next() {
+        Some(offset) => offset.trim().parse()?,
+        None => previous_end,
+    };
+    Ok(ByteRange { length, offset })
+}
+
+fn parse_iv(value: &str) -> Result<[u8; 16]> {
+    let hex = value.trim_start_matches("0x").trim_start_matches("0X");
+    // Checked up front, slicing by byte offsets would panic on multi-byte characters.
+    if hex.len() > 32 || hex.len() % 2 != 0 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(eyre!("invalid IV {}", value));
+    }
+    let mut iv = [0; 16];
+    let start = 16 - hex.len() / 2;
+    for (i, byte) in iv[start..].iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16)?;
+    }
+    Ok(iv)
+}
+
+fn parse_playlist(base: &Url, text: &str) -> Result<Playlist> {
+    let mut lines = text.lines().map(str::trim).filter(|line| !line.is_empty());
+    if lines.next() != Some("#EXTM3U") {
+        return Err(eyre!("{} isn't an HLS playlist", base));
+    }
+
+    let mut variants = Vec::new();
+    let mut renditions = Vec::new();
+    let mut media = MediaPlaylist::default();
+    let mut is_master = false;
+
+    let mut pending_variant = None;
+    let mut pending_byte_range = None;
+    let mut previous_range_end = 0;
+    let mut key = Key::None;
+    let mut sequence = 0;
+
+    for line in lines {
+        if let Some(tag) = line.strip_prefix('#') {
+            let (name, value) = match tag.find(':') {
+                Some(colon) => (&tag[..colon], &tag[colon + 1..]),
+                None => (tag, ""),
+            };
+            match name {
+                "EXT-X-STREAM-INF" => {
+                    is_master = true;
+                    pending_variant = Some(parse_attributes(value));
+                }
+                "EXT-X-MEDIA" => {
+                    is_master = true;
+                    let attributes = parse_attributes(value);
+                    if let (Some("AUDIO"), Some(uri), Some(group_id)) = (
+                        attributes.get("TYPE").map(String::as_str),
+                        attributes.get("URI"),
+                        attributes.get("GROUP-ID"),
+                    ) {
+                        renditions.push(Rendition {
+                            uri: base.join(uri)?,
+                            group_id: group_id.to_owned(),
+                            is_default: attributes.get("DEFAULT").map(String::as_str)
+                                == Some("YES"),
+                        });
+                    }
+                }
+                "EXT-X-MEDIA-SEQUENCE" => sequence = value.trim().parse()?,
+                "EXT-X-BYTERANGE" => {
+                    pending_byte_range = Some(parse_byte_range(value, previous_range_end)?);
+                }
+                "EXT-X-MAP" => {
+                    let attributes = parse_attributes(value);
+                    let uri = attributes
+                        .get("URI")
+                        .ok_or_else(|| eyre!("EXT-X-MAP without a URI"))?;
+                    let byte_range = attributes
+                        .get("BYTERANGE")
+                        .map(|range| parse_byte_range(range, 0))
+                        .transpose()?;
+                    media.map = Some((base.join(uri)?, byte_range));
+                }
+                "EXT-X-KEY" => {
+                    let attributes = parse_attributes(value);
+                    key = match attributes.get("METHOD").map(String::as_str) {
+                        Some("NONE") => Key::None,
+                        Some("AES-128") => Key::Aes128 {
+                            uri: base.join(
+                                attributes
+                                    .get("URI")
+                                    .ok_or_else(|| eyre!("EXT-X-KEY without a URI"))?,
+                            )?,
+                            iv: attributes.get("IV").map(|iv| parse_iv(iv)).transpose()?,
+                        },
+                        method => return Err(eyre!("unsupported encryption {:?}", method)),
+                    };
+                }
+                _ => {}
+            }
+        } else if let Some(attributes) = pending_variant.take() {
+            variants.push(Variant {
+                uri: base.join(line)?,
+                bandwidth: attributes
+                    .get("BANDWIDTH")
+                    .and_then(|bandwidth| bandwidth.parse().ok())
+                    .unwrap_or(0),
+                audio_group: attributes.get("AUDIO").cloned(),
+            });
+        } else {
+            let byte_range = pending_byte_range.take();
+            if let Some(ByteRange { length, offset }) = byte_range {
+                previous_range_end = offset + length;
+            }
+            media.segments.push(Segment {
+                uri: base.join(line)?,
+                byte_range,
+                key: key.clone(),
+                sequence,
+            });
+            sequence += 1;
+        }
+    }
+
+    Ok(if is_master {
+        Playlist::Master {
+            variants,
+            renditions,
+        }
+    } else {
+        Playlist::Media(media)
+    })
+}
+
+fn get(client: &Client, url: &Url, byte_range: Option<&ByteRange>) -> Result<Vec<u8>> {
+    let mut request = client.get(url.clone());
+    if let Some(ByteRange { length, offset }) = byte_range {
+        request = request.header(
+            header::RANGE,
+            format!("bytes={}-{}", offset, offset + length.saturating_sub(1)),
+        );
+    }
+    Ok(request.send()?.error_for_status()?.bytes()?.to_vec())
+}
+
+fn get_with_retries(client: &Client, url: &Url, byte_range: Option<&ByteRange>) -> Result<Vec<u8>> {
+    let mut attempt = 0;
+    loop {
+        match get(client, url, byte_range) {
+            Ok(data) => return Ok(data),
+            Err(e) if attempt < RETRIES => {
+                attempt += 1;
+                eprintln!("retrying {} ({}/{}): {}", url, attempt, RETRIES, e);
+                thread::sleep(RETRY_DELAY * 2u32.pow(attempt - 1));
+            }
+            Err(e) => return Err(e.wrap_err(format!("failed to fetch {}", url))),
+        }
+    }
+}
+
+/// Follows master playlists down to a media playlist, picking the highest bandwidth variant.
+fn resolve_media_playlist(client: &Client, url: &Url) -> Result<MediaPlaylist> {
+    let mut url = url.clone();
+    // A master playlist can't point at another master playlist, so this is only ever two levels deep.
+    for _ in 0..2 {
+        let text = String::from_utf8(get_with_retries(client, &url, None)?)?;
+        match parse_playlist(&url, &text)? {
+            Playlist::Media(media) => return Ok(media),
+            Playlist::Master {
+                variants,
+                renditions,
+            } => {
+                let variant = variants
+                    .into_iter()
+                    .max_by_key(|variant| variant.bandwidth)
+                    .ok_or_else(|| eyre!("{} has no variants", url))?;
+                // Audio-only renditions are preferred over muxed variants when there are any.
+                let rendition = variant.audio_group.as_ref().and_then(|group| {
+                    let mut group = renditions.iter().filter(|r| &r.group_id == group);
+                    let first = group.clone().next();
+                    group.find(|r| r.is_default).or(first)
+                });
+                url = rendition.map_or(variant.uri, |rendition| rendition.uri.clone());
+            }
+        }
+    }
+    Err(eyre!("{} nests master playlists", url))
+}
+
+fn decrypt(
+    client: &Client,
+    keys: &Mutex<HashMap<Url, Vec<u8>>>,
+    segment: &Segment,
+    data: Vec<u8>,
+) -> Result<Vec<u8>> {
+    let (uri, iv) = match &segment.key {
+        Key::None => return Ok(data),
+        Key::Aes128 { uri, iv } => (uri, iv),
+    };
+
+    let cached = keys.lock().unwrap().get(uri).cloned();
+    let key = match cached {
+        Some(key) => key,
+        None => {
+            let key = get_with_retries(client, uri, None)?;
+            keys.lock().unwrap().insert(uri.clone(), key.clone());
+            key
+        }
+    };
+    // Without an explicit IV the media sequence number is used, as a big-endian 128 bit integer.
+    let iv = iv.unwrap_or_else(|| (segment.sequence as u128).to_be_bytes());
+
+    Ok(Aes128Cbc::new_var(&key, &iv)?.decrypt_vec(&data)?)
+}
+
+/// Where a download notes how far it got, next to its partial download.
+pub fn progress_path(path: &Path) -> PathBuf {
+    let mut file_name = download::part_path(path)
+        .file_name()
+        .unwrap_or_default()
+        .to_owned();
+    file_name.push(".progress");
+    path.with_file_name(file_name)
+}
+
+/// How many segments a previous attempt wrote, and how many bytes they took up.
+fn read_progress(path: &Path, total: usize) -> Option<(usize, u64)> {
+    let progress = fs::read_to_string(progress_path(path)).ok()?;
+    let mut numbers = progress.split_whitespace().map(str::parse::<u64>);
+    let (written, of, bytes) = (
+        numbers.next()?.ok()?,
+        numbers.next()?.ok()?,
+        numbers.next()?.ok()?,
+    );
+    // A different number of segments means the playlist isn't the one it was downloading.
+    if of != total as u64 || written > of {
+        return None;
+    }
+    Some((written as usize, bytes))
+}
+
+/// Opens the partial download, cut back to the segments a previous attempt finished writing
+/// or started with the init section if there's nothing to pick up.
+fn open_part(client: &Client, playlist: &MediaPlaylist, path: &Path) -> Result<(File, usize, u64)> {
+    let part_path = download::part_path(path);
+    let resumable = read_progress(path, playlist.segments.len()).filter(|(_, bytes)| {
+        fs::metadata(&part_path).map_or(false, |metadata| metadata.len() >= *bytes)
+    });
+    if let Some((written, bytes)) = resumable {
+        let mut file = OpenOptions::new().write(true).open(&part_path)?;
+        // Whatever comes after is a segment that was cut off halfway through.
+        file.set_len(bytes)?;
+        file.seek(SeekFrom::End(0))?;
+        return Ok((file, written, bytes));
+    }
+
+    let mut file = File::create(&part_path)?;
+    let mut bytes = 0;
+    if let Some((uri, byte_range)) = &playlist.map {
+        let data = get_with_retries(client, uri, byte_range.as_ref())?;
+        file.write_all(&data)?;
+        bytes = data.len() as u64;
+    }
+    Ok((file, 0, bytes))
+}
+
+/// Downloads the stream at `url` into `path`, concatenating its segments as they are.
+///
+/// `progress` is called with the number of segments downloaded so far and the total,
+/// and stops the download by returning `false`. Stopped downloads pick up after the last segment they wrote.
+///
+/// Returns where it ended up, which is next to `path` if something else got there first.
+pub fn download(
+    url: &str,
+    path: &Path,
+    mut progress: impl FnMut(u64, Option<u64>) -> bool,
+) -> Result<PathBuf> {
+    let client = Client::builder().timeout(Duration::from_secs(30)).build()?;
+    let url = Url::parse(url)?;
+    let playlist = resolve_media_playlist(&client, &url)?;
+    if playlist.segments.is_empty() {
+        return Err(eyre!("{} has no segments", url));
+    }
+
+    let (mut file, mut written, mut bytes) = open_part(&client, &playlist, path)?;
+
+    let segments = Arc::new(playlist.segments);
+    let total = segments.len();
+    let next = Arc::new(AtomicUsize::new(written));
+    let keys = Arc::new(Mutex::new(HashMap::new()));
+    let (sender, receiver) = mpsc::channel();
+    for _ in 0..CONCURRENCY.min(total - written) {
+        let (client, segments, next, keys, sender) = (
+            client.clone(),
+            Arc::clone(&segments),
+            Arc::clone(&next),
+            Arc::clone(&keys),
+            sender.clone(),
+        );
+        thread::spawn(move || loop {
+            let index = next.fetch_add(1, Ordering::Relaxed);
+            let segment = match segments.get(index) {
+                Some(segment) => segment,
+                None => break,
+            };
+            let result = get_with_retries(&client, &segment.uri, segment.byte_range.as_ref())
+                .and_then(|data| decrypt(&client, &keys, segment, data));
+            let failed = result.is_err();
+            if sender.send((index, result)).is_err() || failed {
+                break;
+            }
+        });
+    }
+    drop(sender);
+
+    // Segments arrive out of order, so the ones that are early wait here until it's their turn.
+    let mut early = BTreeMap::new();
+    let mut stopped = !progress(written as u64, Some(total as u64));
+    for (index, result) in receiver {
+        if stopped {
+            // Dropping the receiver makes the workers give up on their next segment.
+            return Err(eyre!("download interrupted"));
+        }
+        early.insert(index, result.wrap_err(format!("segment {} failed", index))?);
+        while let Some(data) = early.remove(&written) {
+            file.write_all(&data)?;
+            written += 1;
+            bytes += data.len() as u64;
+            fs::write(
+                progress_path(path),
+                format!("{} {} {}", written, total, bytes),
+            )?;
+            stopped = !progress(written as u64, Some(total as u64));
+        }
+    }
+    if written < total {
+        if stopped {
+            return Err(eyre!("download interrupted"));
+        }
+        return Err(eyre!(
+            "only {} of {} segments were downloaded",
+            written,
+            total
+        ));
+    }
+    file.flush()?;
+    drop(file);
+
+    let final_path = download::unique_path(path, |_| false);
+    fs::rename(download::part_path(path), &final_path)?;
+    let _ = fs::remove_file(progress_path(path));
+    Ok(final_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use tiny_http::{Response, Server};
+
+    const KEY: [u8; 16] = *b"0123456789abcdef";
+
+    /// The requested part of `data`, for `Range: bytes=<first>-<last>`.
+    fn range<'a>(data: &'a [u8], header: Option<&str>) -> &'a [u8] {
+        let bounds = match header.and_then(|header| header.strip_prefix("bytes=")) {
+            Some(bounds) => bounds,
+            None => return data,
+        };
+        let mut bounds = bounds.splitn(2, '-').map(|bound| bound.parse().unwrap());
+        let (first, last): (usize, usize) = (bounds.next().unwrap(), bounds.next().unwrap());
+        &data[first..=last]
+    }
+
+    /// Serves `files` from a local server, answering the first requests for a file in `failures` with an error.
+    fn serve(
+        files: HashMap<&'static str, Vec<u8>>,
+        mut failures: HashMap<&'static str, usize>,
+    ) -> Url {
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let base = Url::parse(&format!("http://{}/", server.server_addr())).unwrap();
+        thread::spawn(move || {
+            for request in server.incoming_requests() {
+                let path = request.url().trim_start_matches('/').to_owned();
+                let header = request
+                    .headers()
+                    .iter()
+                    .find(|header| header.field.equiv("Range"))
+                    .map(|header| header.value.as_str().to_owned());
+                let failing = failures.get_mut(path.as_str()).filter(|left| **left > 0);
+                let result = match (files.get(path.as_str()), failing) {
+                    (Some(_), Some(left)) => {
+                        *left -= 1;
+                        request.respond(Response::empty(500))
+                    }
+                    (Some(data), None) => request
+                        .respond(Response::from_data(range(data, header.as_deref()).to_vec())),
+                    (None, _) => request.respond(Response::empty(404)),
+                };
+                result.unwrap();
+            }
+        });
+        base
+    }
+
+    fn encrypt(data: &[u8], iv: [u8; 16]) -> Vec<u8> {
+        Aes128Cbc::new_var(&KEY, &iv).unwrap().encrypt_vec(data)
+    }
+
+    /// A master playlist with a low and a high variant, where the high one has two byte ranges of
+    /// one file, a segment that fails `failures` times and three encrypted segments.
+    /// Returns the stream's url and what it decodes to.
+    fn stream(failures: usize) -> (Url, Vec<u8>) {
+        let segments: Vec<Vec<u8>> = (0..6).map(|i| vec![i; 100 + usize::from(i)]).collect();
+        let explicit_iv = [7; 16];
+        let media = format!(
+            "#EXTM3U\n\
+             #EXT-X-MEDIA-SEQUENCE:10\n\
+             #EXT-X-BYTERANGE:{}@0\n#EXTINF:1,\nranges.bin\n\
+             #EXT-X-BYTERANGE:{}\n#EXTINF:1,\nranges.bin\n\
+             #EXTINF:1,\nflaky.ts\n\
+             #EXT-X-KEY:METHOD=AES-128,URI=\"key.bin\"\n\
+             #EXTINF:1,\n13.ts\n#EXTINF:1,\n14.ts\n\
+             #EXT-X-KEY:METHOD=AES-128,URI=\"key.bin\",IV=0x{}\n\
+             #EXTINF:1,\n15.ts\n",
+            segments[0].len(),
+            segments[1].len(),
+            "07".repeat(16)
+        );
+
+        let mut files = HashMap::new();
+        files.insert(
+            "master.m3u8",
+            b"#EXTM3U\n\
+              #EXT-X-STREAM-INF:BANDWIDTH=64000\nlow.m3u8\n\
+              #EXT-X-STREAM-INF:BANDWIDTH=128000\nhigh.m3u8\n"
+                .to_vec(),
+        );
+        files.insert("low.m3u8", b"#EXTM3U\n#EXTINF:1,\nlow.ts\n".to_vec());
+        files.insert("low.ts", b"the low variant".to_vec());
+        files.insert("high.m3u8", media.into_bytes());
+        files.insert("ranges.bin", [&segments[0][..], &segments[1]].concat());
+        files.insert("flaky.ts", segments[2].clone());
+        // Without an IV the media sequence number is the IV.
+        files.insert("13.ts", encrypt(&segments[3], 13u128.to_be_bytes()));
+        files.insert("14.ts", encrypt(&segments[4], 14u128.to_be_bytes()));
+        files.insert("15.ts", encrypt(&segments[5], explicit_iv));
+        files.insert("key.bin", KEY.to_vec());
+
+        let mut failing = HashMap::new();
+        failing.insert("flaky.ts", failures);
+        let base = serve(files, failing);
+        (base.join("master.m3u8").unwrap(), segments.concat())
+    }
+
+    fn path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("frick-dmca-hls-{}-{}", name, rand::random::<u32>()))
+    }
+
+    #[test]
+    fn downloads_the_best_variant_in_order() {
+        let (url, expected) = stream(1);
+        let path = path("download");
+        let mut last_progress = None;
+        let result = download(url.as_str(), &path, |done, total| {
+            last_progress = Some((done, total));
+            true
+        });
+        let contents = fs::read(&path);
+        let _ = fs::remove_file(&path);
+
+        assert_eq!(result.unwrap(), path);
+        assert_eq!(contents.unwrap(), expected);
+        assert_eq!(last_progress, Some((6, Some(6))));
+    }
+
+    #[test]
+    fn resumes_after_the_last_written_segment() {
+        let (url, expected) = stream(0);
+        let path = path("resume");
+        let stopped = download(url.as_str(), &path, |done, _| done < 3);
+        let mut first_progress = None;
+        let result = download(url.as_str(), &path, |done, total| {
+            first_progress.get_or_insert((done, total));
+            true
+        });
+        let contents = fs::read(&path);
+        let _ = fs::remove_file(&path);
+
+        assert!(stopped.is_err());
+        assert_eq!(result.unwrap(), path);
+        assert_eq!(contents.unwrap(), expected);
+        assert!(matches!(first_progress, Some((done, Some(6))) if done >= 3));
+        assert!(!progress_path(&path).exists());
+    }
+
+    #[test]
+    fn gives_up_after_retries() {
+        let (url, _) = stream(RETRIES as usize + 1);
+        let path = path("retries");
+        let result = download(url.as_str(), &path, |_, _| true);
+        let _ = fs::remove_file(download::part_path(&path));
+        let _ = fs::remove_file(progress_path(&path));
+
+        assert!(result.is_err());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn parses_ivs() {
+        let mut expected = [0; 16];
+        expected[15] = 0x2a;
+        assert_eq!(parse_iv("0x2A").unwrap(), expected);
+        assert!(parse_iv("0x2g").is_err());
+        assert!(parse_iv("0xé0").is_err());
+    }
+}
@@ -0,0 +1,132 @@
+//! Keeping to a single window: the first launch listens on a Unix domain socket,
+//! later ones hand it what they were asked to do and exit.
+//!
+//! Every connection carries a [`Control`] per line as JSON, or nothing when there's nothing to do.
+
+use bridge::Control;
+use color_eyre::eyre::{eyre, Result};
+use std::{
+    env,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, ErrorKind, Write},
+    os::unix::{
+        io::AsRawFd,
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+/// How long a launch gets to say what it wants before it's hung up on.
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// In `$XDG_RUNTIME_DIR`, which only the user can get into,
+/// or the temporary directory with the user's name in it when there isn't one.
+fn path() -> PathBuf {
+    match dirs::runtime_dir() {
+        Some(directory) => directory.join("frick-dmca.sock"),
+        None => env::temp_dir().join(format!(
+            "frick-dmca-{}.sock",
+            env::var("USER").unwrap_or_default()
+        )),
+    }
+}
+
+/// An exclusive `flock` on the file next to the socket, released when it's dropped.
+///
+/// Launches hold it while deciding whether they're the running instance, so two of them
+/// can't both remove a stale socket and then bind one of their own.
+struct SocketLock {
+    /// Closing it releases the lock.
+    _file: File,
+}
+
+impl SocketLock {
+    fn acquire(socket: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .open(socket.with_extension("lock"))?;
+        // Blocks until whoever else is deciding is done, which is quick.
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        Ok(Self { _file: file })
+    }
+}
+
+/// Held by the running instance, the socket is removed when it's dropped.
+pub struct Lock {
+    path: PathBuf,
+    listener: UnixListener,
+}
+
+impl Lock {
+    /// Makes this the running instance, or hands `control` to the one that's already running,
+    /// in which case there's no lock.
+    pub fn acquire(control: Option<&Control>) -> Result<Option<Self>> {
+        let path = path();
+        let _lock = SocketLock::acquire(&path)?;
+        match UnixStream::connect(&path) {
+            Ok(stream) => return forward(stream, control).map(|()| None),
+            // Left behind by an instance that didn't get to clean up.
+            Err(e) if e.kind() == ErrorKind::ConnectionRefused => fs::remove_file(&path)?,
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(eyre!("couldn't reach the running instance: {}", e)),
+        }
+        match UnixListener::bind(&path) {
+            Ok(listener) => Ok(Some(Self { path, listener })),
+            // Another launch got there first without taking the lock, like one of an older version.
+            Err(e) if e.kind() == ErrorKind::AddrInUse => {
+                forward(UnixStream::connect(&path)?, control).map(|()| None)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Passes what later launches forward on to `handler`, from a thread of its own.
+    pub fn serve(&self, handler: impl Fn(Control) + Send + 'static) -> Result<()> {
+        let listener = self.listener.try_clone()?;
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        eprintln!("failed to accept a launch: {}", e);
+                        continue;
+                    }
+                };
+                // Launches are read one at a time, so one that never hangs up would block the rest.
+                if let Err(e) = stream.set_read_timeout(Some(READ_TIMEOUT)) {
+                    eprintln!("failed to set a launch's timeout: {}", e);
+                }
+                for line in BufReader::new(stream).lines() {
+                    match line.map(|line| serde_json::from_str::<Control>(&line)) {
+                        Ok(Ok(control)) => handler(control),
+                        Ok(Err(e)) => eprintln!("ignoring malformed launch: {}", e),
+                        Err(e) => {
+                            eprintln!("failed to read a launch: {}", e);
+                            break;
+                        }
+                    }
+                }
+            }
+        });
+        Ok(())
+    }
+}
+
+/// Hands `control` to the running instance on the other end of `stream`.
+fn forward(mut stream: UnixStream, control: Option<&Control>) -> Result<()> {
+    if let Some(control) = control {
+        writeln!(stream, "{}", serde_json::to_string(control)?)?;
+    }
+    Ok(())
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
@@ -0,0 +1,144 @@
+use crate::{settings, window, State};
+use bridge::{Envelope, HostMessage, Request, Response, RECEIVE_FUNCTION};
+use color_eyre::eyre::{eyre, Result};
+use std::thread;
+use web_view::{Handle, WVResult, WebView};
+
+/// Handles a message sent by the page through `window.external.invoke`.
+///
+/// Requests are served on their own thread so slow ones don't block the UI.
+pub fn handle(webview: &mut WebView<State>, arg: &str) -> WVResult {
+    let Envelope { id, request } = match serde_json::from_str::<Envelope>(arg) {
+        Ok(envelope) => envelope,
+        Err(e) => {
+            eprintln!("ignoring malformed message {}: {}", arg, e);
+            return Ok(());
+        }
+    };
+
+    let handle = webview.handle();
+    let queue = webview.user_data().queue.clone();
+    match request {
+        Request::Fetch { url } => {
+            thread::spawn(move || {
+                reply(&handle, id, fetch(&url).map(|body| Response::Text { body }))
+            });
+        }
+        Request::Download {
+            quality,
+            mime_type,
+            metadata,
+        } => {
+            // Dialogs have to be opened from the UI thread, which is the one we're on.
+            // Failing to open one only fails this request, not the whole app.
+            let response = match webview
+                .dialog()
+                .choose_directory("Download to", dirs::download_dir().as_deref())
+            {
+                Ok(Some(directory)) => Ok(Response::Download {
+                    id: queue.add(quality, mime_type, metadata, directory),
+                }),
+                Ok(None) => Ok(Response::Cancelled),
+                Err(e) => Err(eyre!("couldn't open the directory picker: {}", e)),
+            };
+            reply(&handle, id, response);
+        }
+        Request::ListDownloads => {
+            let (downloads, parallelism) = queue.list();
+            reply(
+                &handle,
+                id,
+                Ok(Response::Downloads {
+                    downloads,
+                    parallelism,
+                }),
+            );
+        }
+        Request::PauseDownload { id } => queue.pause(id),
+        Request::ResumeDownload { id } => queue.resume(id),
+        Request::CancelDownload { id } => queue.cancel(id),
+        Request::RemoveDownload { id } => queue.remove(id),
+        Request::SetDownloadParallelism { parallelism } => queue.set_parallelism(parallelism),
+        Request::ReportPlayback {
+            song,
+            status,
+            position,
+            duration,
+        } => {
+            webview.set_title(&window::title(song.as_ref(), status))?;
+            #[cfg(target_os = "linux")]
+            {
+                if let Some(mpris) = &webview.user_data().mpris {
+                    mpris.update(song, status, position, duration);
+                }
+            }
+            #[cfg(not(target_os = "linux"))]
+            let _ = (position, duration);
+        }
+        Request::Notify { song } => {
+            #[cfg(target_os = "linux")]
+            {
+                if let Some(notifier) = &webview.user_data().notifier {
+                    notifier.show(song);
+                }
+            }
+            #[cfg(not(target_os = "linux"))]
+            let _ = song;
+        }
+        Request::GetWindow => {
+            let geometry = webview.user_data().window.clone();
+            reply(&handle, id, Ok(Response::Window { geometry }));
+        }
+        Request::GetSettings => {
+            let settings = webview.user_data().settings.clone();
+            reply(&handle, id, Ok(Response::Settings { settings }));
+        }
+        Request::SaveSettings { settings } => {
+            let result = settings::save(&settings);
+            if result.is_ok() {
+                webview.user_data_mut().settings = settings;
+            }
+            reply(&handle, id, result.map(|()| Response::Saved));
+        }
+        Request::SaveWindow { geometry } => {
+            if geometry != webview.user_data().window {
+                if let Err(e) = window::save(&geometry) {
+                    eprintln!("failed to save the window's geometry: {}", e);
+                }
+                webview.user_data_mut().window = geometry;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn reply<T>(handle: &Handle<T>, id: Option<u32>, result: Result<Response>) {
+    if let Some(id) = id {
+        let response = result.unwrap_or_else(|e| Response::Error {
+            message: e.to_string(),
+        });
+        if let Err(e) = post(handle, &HostMessage::Reply { id, response }) {
+            eprintln!("failed to reply to request {}: {}", id, e);
+        }
+    }
+}
+
+fn fetch(url: &str) -> Result<String> {
+    if !(url.starts_with("https://") || url.starts_with("http://")) {
+        return Err(eyre!("refusing to fetch {}", url));
+    }
+    Ok(reqwest::blocking::get(url)?.error_for_status()?.text()?)
+}
+
+/// Delivers a message to the page.
+pub fn post<T>(handle: &Handle<T>, message: &HostMessage) -> Result<()> {
+    // The message is passed as a JS string literal, which is what JSON strings are.
+    let script = format!(
+        "window.{}({})",
+        RECEIVE_FUNCTION,
+        serde_json::to_string(&serde_json::to_string(message)?)?
+    );
+    handle.dispatch(move |webview| webview.eval(&script))?;
+    Ok(())
+}
@@ -3,25 +3,125 @@
     windows_subsystem = "windows"
 )]
 
+mod cli;
+mod desktop;
+mod download;
+mod hls;
+#[cfg(unix)]
+mod instance;
+mod invoke;
+#[cfg(target_os = "linux")]
+mod mpris;
+#[cfg(target_os = "linux")]
+mod notify;
+mod proxy;
+mod queue;
+mod remux;
+mod resolve;
+mod settings;
+mod window;
+
+#[cfg(unix)]
+use bridge::{Control, Event, HostMessage};
+use cli::Command;
 use rust_embed::RustEmbed;
-use color_eyre::eyre::{eyre, Result};
+use color_eyre::eyre::Result;
+use structopt::StructOpt;
 use web_view::*;
 
 #[derive(RustEmbed)]
 #[folder = "$APP_HTML_DIR"]
 struct Asset;
 
+/// Shared between the UI thread and everything reporting back to the page.
+pub struct State {
+    pub queue: queue::Queue,
+    pub window: bridge::WindowGeometry,
+    pub settings: bridge::Settings,
+    /// Missing when there's no session bus to register on.
+    #[cfg(target_os = "linux")]
+    pub mpris: Option<mpris::Mpris>,
+    #[cfg(target_os = "linux")]
+    pub notifier: Option<notify::Notifier>,
+}
+
 fn main() -> Result<()> {
-    let index_content = Asset::get("index.html").ok_or_else(|| eyre!("index.html not found"))?;
+    match cli::Options::from_args().command {
+        None => run_app(None),
+        Some(Command::Info { url }) => cli::info(&url),
+        Some(Command::Get {
+            url,
+            quality,
+            output,
+        }) => cli::get(&url, quality, output),
+        Some(Command::Play { url }) => run_app(Some(&url)),
+        Some(Command::Open { target }) => run_app(Some(&desktop::song_url(&target)?)),
+        Some(Command::InstallDesktopEntry { playlists }) => desktop::install(playlists),
+    }
+}
 
-    web_view::builder()
-        .title("Minimal webview example")
-        .content(Content::Html(std::str::from_utf8(index_content.as_ref())?))
-        .size(800, 600)
+/// Opens the app with the song at `url` loaded, or hands it to the app if it's already open.
+fn run_app(url: Option<&str>) -> Result<()> {
+    #[cfg(unix)]
+    let lock = {
+        let open = url.map(|url| Control::Open {
+            url: url.to_owned(),
+        });
+        match instance::Lock::acquire(open.as_ref())? {
+            Some(lock) => lock,
+            None => return Ok(()),
+        }
+    };
+
+    let server = proxy::Server::start()?;
+    let query = url.iter().map(|url| ("url", *url)).collect::<Vec<_>>();
+
+    // The page restores the position and whether the window was maximized, web-view can't do either.
+    let geometry = window::load();
+    let mut webview = web_view::builder()
+        .title(window::DEFAULT_TITLE)
+        .content(Content::Url(server.index_url(&query)))
+        .size(geometry.width, geometry.height)
         .resizable(true)
-        .user_data(())
-        .invoke_handler(|_webview, _arg| Ok(()))
-        .run()?;
+        .user_data(State {
+            queue: queue::Queue::load()?,
+            window: geometry,
+            settings: settings::load(),
+            #[cfg(target_os = "linux")]
+            mpris: None,
+            #[cfg(target_os = "linux")]
+            notifier: None,
+        })
+        .invoke_handler(invoke::handle)
+        .build()?;
+    webview.user_data().queue.attach(webview.handle());
+    #[cfg(target_os = "linux")]
+    {
+        match mpris::Mpris::start(control(webview.handle())) {
+            Ok(mpris) => webview.user_data_mut().mpris = Some(mpris),
+            Err(e) => eprintln!("failed to register on the session bus: {}", e),
+        }
+        match notify::Notifier::start(control(webview.handle())) {
+            Ok(notifier) => webview.user_data_mut().notifier = Some(notifier),
+            Err(e) => eprintln!("failed to connect to the notification server: {}", e),
+        }
+    }
+    #[cfg(unix)]
+    lock.serve(control(webview.handle()))?;
+    webview.run()?;
 
     Ok(())
 }
+
+/// Passes controls from outside the window, like media keys or later launches, on to the page.
+#[cfg(unix)]
+fn control(handle: Handle<State>) -> impl Fn(Control) + Send + 'static {
+    move |control| {
+        let message = HostMessage::Event {
+            event: Event::Control { control },
+        };
+        if let Err(e) = invoke::post(&handle, &message) {
+            eprintln!("failed to pass on a media control: {}", e);
+        }
+    }
+}
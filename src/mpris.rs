@@ -0,0 +1,504 @@
+//! An MPRIS service on the session bus, so `playerctl` and desktop widgets can see and control the player.
+//!
+//! The page reports what it's playing with [`bridge::Request::ReportPlayback`],
+//! calls from the bus are forwarded to it as [`bridge::Event::Control`]s.
+
+use bridge::{Control, Metadata, PlaybackStatus};
+use color_eyre::eyre::Result;
+use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    sync::{Arc, Mutex},
+    thread,
+    time::Instant,
+};
+use zbus::{dbus_interface, fdo, Connection, ObjectServer};
+use zvariant::{ObjectPath, Value};
+
+const BUS_NAME: &str = "org.mpris.MediaPlayer2.frickdmca";
+const PATH: &str = "/org/mpris/MediaPlayer2";
+const PLAYER_INTERFACE: &str = "org.mpris.MediaPlayer2.Player";
+/// Reported positions further than this from where the song should be are announced as seeks.
+const SEEK_THRESHOLD: f64 = 1.0;
+
+fn micros(seconds: f64) -> i64 {
+    (seconds * 1_000_000.0) as i64
+}
+
+/// What the page last reported.
+struct Playback {
+    song: Option<Metadata>,
+    /// Bumped for every song, clients tell songs apart by their track id.
+    track: u32,
+    status: PlaybackStatus,
+    position: f64,
+    duration: f64,
+    /// Playing songs have moved on from `position` since.
+    reported_at: Instant,
+}
+
+impl Default for Playback {
+    fn default() -> Self {
+        Self {
+            song: None,
+            track: 0,
+            status: PlaybackStatus::Stopped,
+            position: 0.0,
+            duration: 0.0,
+            reported_at: Instant::now(),
+        }
+    }
+}
+
+impl Playback {
+    fn position(&self) -> f64 {
+        let position = match self.status {
+            PlaybackStatus::Playing => self.position + self.reported_at.elapsed().as_secs_f64(),
+            _ => self.position,
+        };
+        if self.duration > 0.0 {
+            position.min(self.duration)
+        } else {
+            position
+        }
+    }
+
+    fn status(&self) -> &'static str {
+        match self.status {
+            PlaybackStatus::Playing => "Playing",
+            PlaybackStatus::Paused => "Paused",
+            PlaybackStatus::Stopped => "Stopped",
+        }
+    }
+
+    fn metadata(&self) -> HashMap<String, Value<'static>> {
+        let mut metadata = HashMap::new();
+        let song = match &self.song {
+            Some(song) => song,
+            // Clients take a missing track id to mean nothing's loaded.
+            None => return metadata,
+        };
+        let track_id = format!("/org/frickdmca/track/{}", self.track);
+        if let Ok(track_id) = ObjectPath::try_from(track_id) {
+            metadata.insert("mpris:trackid".to_owned(), Value::from(track_id));
+        }
+        if self.duration > 0.0 {
+            metadata.insert(
+                "mpris:length".to_owned(),
+                Value::from(micros(self.duration)),
+            );
+        }
+        metadata.insert("xesam:title".to_owned(), Value::from(song.title.clone()));
+        if let Some(artist) = &song.artist {
+            metadata.insert("xesam:artist".to_owned(), Value::from(vec![artist.clone()]));
+        }
+        metadata.insert("xesam:url".to_owned(), Value::from(song.source_url.clone()));
+        if let Some(artwork_url) = &song.artwork_url {
+            metadata.insert("mpris:artUrl".to_owned(), Value::from(artwork_url.clone()));
+        }
+        metadata
+    }
+}
+
+struct Root;
+
+#[dbus_interface(name = "org.mpris.MediaPlayer2")]
+impl Root {
+    /// The window can't be raised from another thread, so this does nothing, as allowed by `CanRaise`.
+    fn raise(&self) {}
+
+    fn quit(&self) {}
+
+    #[dbus_interface(property)]
+    fn can_quit(&self) -> bool {
+        false
+    }
+
+    #[dbus_interface(property)]
+    fn can_raise(&self) -> bool {
+        false
+    }
+
+    #[dbus_interface(property)]
+    fn has_track_list(&self) -> bool {
+        false
+    }
+
+    #[dbus_interface(property)]
+    fn identity(&self) -> String {
+        "frick-dmca".to_owned()
+    }
+
+    #[dbus_interface(property)]
+    fn desktop_entry(&self) -> String {
+        "frick-dmca".to_owned()
+    }
+
+    #[dbus_interface(property)]
+    fn supported_uri_schemes(&self) -> Vec<String> {
+        vec!["https".to_owned()]
+    }
+
+    #[dbus_interface(property)]
+    fn supported_mime_types(&self) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+struct Player {
+    playback: Arc<Mutex<Playback>>,
+    control: Box<dyn Fn(Control) + Send>,
+}
+
+#[dbus_interface(name = "org.mpris.MediaPlayer2.Player")]
+impl Player {
+    fn next(&self) {
+        (self.control)(Control::Next);
+    }
+
+    fn previous(&self) {
+        (self.control)(Control::Previous);
+    }
+
+    fn pause(&self) {
+        (self.control)(Control::Pause);
+    }
+
+    fn play_pause(&self) {
+        (self.control)(Control::PlayPause);
+    }
+
+    fn stop(&self) {
+        (self.control)(Control::Stop);
+    }
+
+    fn play(&self) {
+        (self.control)(Control::Play);
+    }
+
+    /// `offset` is in microseconds, like every other time in MPRIS.
+    fn seek(&self, offset: i64) {
+        (self.control)(Control::Seek {
+            offset: offset as f64 / 1_000_000.0,
+        });
+    }
+
+    fn set_position(&self, _track_id: ObjectPath, position: i64) {
+        (self.control)(Control::SetPosition {
+            position: position as f64 / 1_000_000.0,
+        });
+    }
+
+    fn open_uri(&self, uri: String) {
+        (self.control)(Control::Open { url: uri });
+    }
+
+    #[dbus_interface(property)]
+    fn playback_status(&self) -> String {
+        self.playback.lock().unwrap().status().to_owned()
+    }
+
+    #[dbus_interface(property)]
+    fn rate(&self) -> f64 {
+        1.0
+    }
+
+    #[dbus_interface(property)]
+    fn minimum_rate(&self) -> f64 {
+        1.0
+    }
+
+    #[dbus_interface(property)]
+    fn maximum_rate(&self) -> f64 {
+        1.0
+    }
+
+    #[dbus_interface(property)]
+    fn metadata(&self) -> HashMap<String, Value> {
+        self.playback.lock().unwrap().metadata()
+    }
+
+    #[dbus_interface(property)]
+    fn volume(&self) -> f64 {
+        1.0
+    }
+
+    #[dbus_interface(property)]
+    fn position(&self) -> i64 {
+        micros(self.playback.lock().unwrap().position())
+    }
+
+    #[dbus_interface(property)]
+    fn can_go_next(&self) -> bool {
+        true
+    }
+
+    #[dbus_interface(property)]
+    fn can_go_previous(&self) -> bool {
+        true
+    }
+
+    #[dbus_interface(property)]
+    fn can_play(&self) -> bool {
+        self.playback.lock().unwrap().song.is_some()
+    }
+
+    #[dbus_interface(property)]
+    fn can_pause(&self) -> bool {
+        self.playback.lock().unwrap().song.is_some()
+    }
+
+    #[dbus_interface(property)]
+    fn can_seek(&self) -> bool {
+        self.playback.lock().unwrap().duration > 0.0
+    }
+
+    #[dbus_interface(property)]
+    fn can_control(&self) -> bool {
+        true
+    }
+}
+
+pub struct Mpris {
+    connection: Connection,
+    playback: Arc<Mutex<Playback>>,
+}
+
+impl Mpris {
+    /// Registers the player on the session bus, passing calls from it to `control`.
+    pub fn start(control: impl Fn(Control) + Send + 'static) -> Result<Self> {
+        Self::start_on(Connection::new_session()?, control)
+    }
+
+    /// Registers the player on `connection` and serves calls to it from a thread of its own.
+    pub fn start_on(
+        connection: Connection,
+        control: impl Fn(Control) + Send + 'static,
+    ) -> Result<Self> {
+        fdo::DBusProxy::new(&connection)?
+            .request_name(BUS_NAME, fdo::RequestNameFlags::DoNotQueue.into())?;
+        let playback = Arc::new(Mutex::new(Playback::default()));
+
+        let player = Player {
+            playback: Arc::clone(&playback),
+            control: Box::new(control),
+        };
+        let server_connection = connection.clone();
+        thread::spawn(move || {
+            let mut server = ObjectServer::new(&server_connection);
+            let path = ObjectPath::try_from(PATH).unwrap();
+            if let Err(e) = server
+                .at(&path, Root)
+                .and_then(|_| server.at(&path, player))
+            {
+                eprintln!("failed to serve MPRIS: {}", e);
+                return;
+            }
+            loop {
+                match server.try_handle_next() {
+                    Ok(_) => {}
+                    // The connection is gone, so there's nothing more to handle.
+                    Err(zbus::Error::Io(e)) => {
+                        return eprintln!("lost the MPRIS connection: {}", e);
+                    }
+                    Err(e) => eprintln!("failed to handle an MPRIS call: {}", e),
+                }
+            }
+        });
+
+        Ok(Self {
+            connection,
+            playback,
+        })
+    }
+
+    /// Takes in what the page reported and tells clients about what changed.
+    pub fn update(
+        &self,
+        song: Option<Metadata>,
+        status: PlaybackStatus,
+        position: f64,
+        duration: f64,
+    ) {
+        let mut playback = self.playback.lock().unwrap();
+        let song_changed = playback.song.as_ref().map(|song| &song.source_url)
+            != song.as_ref().map(|song| &song.source_url);
+        let seeked = !song_changed && (playback.position() - position).abs() > SEEK_THRESHOLD;
+        if song_changed {
+            playback.track += 1;
+        }
+        playback.song = song;
+        playback.status = status;
+        playback.position = position;
+        playback.duration = duration;
+        playback.reported_at = Instant::now();
+
+        if let Err(e) = self.announce(&playback, seeked) {
+            eprintln!("failed to announce MPRIS changes: {}", e);
+        }
+    }
+
+    fn announce(&self, playback: &Playback, seeked: bool) -> Result<()> {
+        let mut changed = HashMap::new();
+        changed.insert("PlaybackStatus", Value::from(playback.status()));
+        changed.insert("Metadata", Value::from(playback.metadata()));
+        changed.insert("CanPlay", Value::from(playback.song.is_some()));
+        changed.insert("CanPause", Value::from(playback.song.is_some()));
+        changed.insert("CanSeek", Value::from(playback.duration > 0.0));
+        self.connection.emit_signal(
+            None,
+            PATH,
+            "org.freedesktop.DBus.Properties",
+            "PropertiesChanged",
+            &(PLAYER_INTERFACE, changed, Vec::<&str>::new()),
+        )?;
+        // Position isn't announced as it changes, only when it jumps.
+        if seeked {
+            self.connection.emit_signal(
+                None,
+                PATH,
+                PLAYER_INTERFACE,
+                "Seeked",
+                &(micros(playback.position),),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::{BufRead, BufReader},
+        process::{Child, Command, Stdio},
+        sync::mpsc,
+        time::Duration,
+    };
+    use zbus::dbus_proxy;
+    use zvariant::OwnedValue;
+
+    #[dbus_proxy(
+        interface = "org.mpris.MediaPlayer2.Player",
+        default_service = "org.mpris.MediaPlayer2.frickdmca",
+        default_path = "/org/mpris/MediaPlayer2"
+    )]
+    trait MprisPlayer {
+        fn play_pause(&self) -> zbus::Result<()>;
+
+        fn seek(&self, offset: i64) -> zbus::Result<()>;
+
+        fn set_position(&self, track_id: &ObjectPath<'_>, position: i64) -> zbus::Result<()>;
+
+        #[dbus_proxy(property)]
+        fn playback_status(&self) -> zbus::Result<String>;
+
+        #[dbus_proxy(property)]
+        fn metadata(&self) -> zbus::Result<HashMap<String, OwnedValue>>;
+
+        #[dbus_proxy(property)]
+        fn position(&self) -> zbus::Result<i64>;
+    }
+
+    /// A private session bus, so the test doesn't need or touch the user's.
+    struct Bus {
+        daemon: Child,
+        address: String,
+    }
+
+    impl Bus {
+        fn start() -> Self {
+            let mut daemon = Command::new("dbus-daemon")
+                .args(&["--session", "--nofork", "--print-address"])
+                .stdout(Stdio::piped())
+                .spawn()
+                .expect("couldn't run dbus-daemon");
+            let mut address = String::new();
+            BufReader::new(daemon.stdout.take().unwrap())
+                .read_line(&mut address)
+                .unwrap();
+            Self {
+                daemon,
+                address: address.trim().to_owned(),
+            }
+        }
+
+        fn connect(&self) -> Connection {
+            Connection::new_for_address(&self.address, true).unwrap()
+        }
+    }
+
+    impl Drop for Bus {
+        fn drop(&mut self) {
+            let _ = self.daemon.kill();
+            let _ = self.daemon.wait();
+        }
+    }
+
+    fn song() -> Metadata {
+        Metadata {
+            title: "Title".to_owned(),
+            artist: Some("Artist".to_owned()),
+            source_url: "https://soundcloud.com/artist/title".to_owned(),
+            artwork_url: None,
+        }
+    }
+
+    /// The offset of the next `Seeked` signal `connection` receives, in microseconds.
+    fn next_seek(connection: &Connection) -> i64 {
+        loop {
+            let message = connection.receive_message().unwrap();
+            if message.header().unwrap().member().unwrap() == Some("Seeked") {
+                return message.body().unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn serves_the_player() {
+        let bus = Bus::start();
+        let (sender, receiver) = mpsc::channel();
+        let mpris = Mpris::start_on(bus.connect(), move |control| {
+            sender.send(control).unwrap();
+        })
+        .unwrap();
+
+        let client = bus.connect();
+        fdo::DBusProxy::new(&client)
+            .unwrap()
+            .add_match(&format!(
+                "type='signal',interface='{}',member='Seeked'",
+                PLAYER_INTERFACE
+            ))
+            .unwrap();
+        let player = MprisPlayerProxy::new(&client).unwrap();
+        let next_control = || receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+
+        player.play_pause().unwrap();
+        assert!(matches!(next_control(), Control::PlayPause));
+        player.seek(-5_000_000).unwrap();
+        assert!(matches!(next_control(), Control::Seek { offset } if offset == -5.0));
+        let track_id = ObjectPath::try_from("/org/frickdmca/track/1").unwrap();
+        player.set_position(&track_id, 30_000_000).unwrap();
+        assert!(matches!(next_control(), Control::SetPosition { position } if position == 30.0));
+
+        assert!(player.metadata().unwrap().is_empty());
+        mpris.update(Some(song()), PlaybackStatus::Paused, 10.0, 120.0);
+        assert_eq!(player.playback_status().unwrap(), "Paused");
+        assert_eq!(player.position().unwrap(), 10_000_000);
+        let metadata = player.metadata().unwrap();
+        assert_eq!(*metadata["mpris:trackid"], Value::from(track_id));
+        assert_eq!(*metadata["mpris:length"], Value::from(120_000_000i64));
+        assert_eq!(*metadata["xesam:title"], Value::from("Title"));
+        assert_eq!(
+            *metadata["xesam:artist"],
+            Value::from(vec!["Artist".to_owned()])
+        );
+
+        // Only the jump to 60 seconds is a seek, so it's the first one announced.
+        mpris.update(Some(song()), PlaybackStatus::Paused, 10.5, 120.0);
+        mpris.update(Some(song()), PlaybackStatus::Paused, 60.0, 120.0);
+        assert_eq!(next_seek(&client), 60_000_000);
+        assert_eq!(player.position().unwrap(), 60_000_000);
+    }
+}
@@ -0,0 +1,191 @@
+//! Desktop notifications for new songs, through `org.freedesktop.Notifications`.
+
+use crate::download;
+use bridge::{Control, Metadata};
+use color_eyre::eyre::{eyre, Result};
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    fs,
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+    sync::{mpsc, Arc, Mutex},
+    thread,
+};
+use zbus::{dbus_proxy, fdo, Connection};
+use zvariant::Value;
+
+/// Notifications disappear after as long as the server thinks is right.
+const DEFAULT_TIMEOUT: i32 = -1;
+const PAUSE_ACTION: &str = "pause";
+const NEXT_ACTION: &str = "next";
+
+#[dbus_proxy(
+    interface = "org.freedesktop.Notifications",
+    default_service = "org.freedesktop.Notifications",
+    default_path = "/org/freedesktop/Notifications"
+)]
+trait Notifications {
+    #[allow(clippy::too_many_arguments)]
+    fn notify(
+        &self,
+        app_name: &str,
+        replaces_id: u32,
+        app_icon: &str,
+        summary: &str,
+        body: &str,
+        actions: &[&str],
+        hints: HashMap<&str, &Value>,
+        expire_timeout: i32,
+    ) -> zbus::Result<u32>;
+
+    #[dbus_proxy(signal)]
+    fn action_invoked(&self, id: u32, action_key: String) -> zbus::Result<()>;
+}
+
+pub struct Notifier {
+    /// Songs to show, one after the other on a thread of their own.
+    songs: mpsc::Sender<Metadata>,
+}
+
+impl Notifier {
+    /// Connects to the session bus, passing clicks on a notification's buttons to `control`.
+    pub fn start(control: impl Fn(Control) + Send + 'static) -> Result<Self> {
+        let current = Arc::new(Mutex::new(0));
+
+        // Waiting for signals blocks the connection, so they get one of their own.
+        let signals = Connection::new_session()?;
+        fdo::DBusProxy::new(&signals)?.add_match(
+            "type='signal',interface='org.freedesktop.Notifications',member='ActionInvoked'",
+        )?;
+        let listening = Arc::clone(&current);
+        thread::spawn(move || {
+            let proxy = match NotificationsProxy::new(&signals) {
+                Ok(proxy) => proxy,
+                Err(e) => return eprintln!("failed to listen to notifications: {}", e),
+            };
+            let connected = proxy.connect_action_invoked(move |id, action| {
+                if id != *listening.lock().unwrap() {
+                    return Ok(());
+                }
+                match action.as_str() {
+                    PAUSE_ACTION => control(Control::Pause),
+                    NEXT_ACTION => control(Control::Next),
+                    _ => {}
+                }
+                Ok(())
+            });
+            if let Err(e) = connected {
+                return eprintln!("failed to listen to notifications: {}", e);
+            }
+            loop {
+                match proxy.next_signal() {
+                    Ok(_) => {}
+                    // The connection is gone, so no more signals are coming.
+                    Err(zbus::Error::Io(e)) => {
+                        return eprintln!("lost the notifications connection: {}", e);
+                    }
+                    Err(e) => eprintln!("failed to receive a notification signal: {}", e),
+                }
+            }
+        });
+
+        let connection = Connection::new_session()?;
+        let (songs, receiver) = mpsc::channel::<Metadata>();
+        thread::spawn(move || {
+            let mut artwork = Vec::new();
+            while let Ok(song) = receiver.recv() {
+                // Only the newest of the songs that came in while the last one was shown is still playing.
+                let song = receiver.try_iter().last().unwrap_or(song);
+                if let Err(e) = notify(&connection, &current, &mut artwork, &song) {
+                    eprintln!("failed to show a notification for {}: {}", song.title, e);
+                }
+            }
+        });
+        Ok(Self { songs })
+    }
+
+    /// Shows `song` in a notification, once its artwork has been fetched.
+    pub fn show(&self, song: Metadata) {
+        // The worker only goes away with the notifier.
+        let _ = self.songs.send(song);
+    }
+}
+
+/// `artwork` holds the images of the last notifications, the oldest first.
+fn notify(
+    connection: &Connection,
+    current: &Mutex<u32>,
+    artwork: &mut Vec<PathBuf>,
+    song: &Metadata,
+) -> Result<()> {
+    // Notification servers only load images from files.
+    let path = song.artwork_url.as_deref().and_then(|url| {
+        fetch_artwork(url)
+            .map_err(|e| eprintln!("failed to fetch artwork from {}: {}", url, e))
+            .ok()
+    });
+    if let Some(path) = &path {
+        remember_artwork(artwork, path);
+    }
+    let artwork = path.map(|path| format!("file://{}", path.display()));
+    let artwork = artwork.as_deref().map(Value::from);
+    let mut hints = HashMap::new();
+    if let Some(artwork) = &artwork {
+        hints.insert("image-path", artwork);
+    }
+
+    let proxy = NotificationsProxy::new(connection)?;
+    let replaces = *current.lock().unwrap();
+    let id = proxy.notify(
+        "frick-dmca",
+        replaces,
+        "frick-dmca",
+        &song.title,
+        song.artist.as_deref().unwrap_or(""),
+        &[PAUSE_ACTION, "Pause", NEXT_ACTION, "Skip"],
+        hints,
+        DEFAULT_TIMEOUT,
+    )?;
+    *current.lock().unwrap() = id;
+    Ok(())
+}
+
+/// Keeps the artwork of the current and the previous notification, which the server might
+/// still be loading, and removes every other image from the cache, including earlier runs' ones.
+fn remember_artwork(artwork: &mut Vec<PathBuf>, path: &Path) {
+    artwork.retain(|kept| kept != path);
+    artwork.push(path.to_owned());
+    if artwork.len() > 2 {
+        artwork.remove(0);
+    }
+    let entries = match path.parent().map(fs::read_dir) {
+        Some(Ok(entries)) => entries,
+        _ => return,
+    };
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        if !artwork.contains(&entry.path()) {
+            let _ = fs::remove_file(entry.path());
+        }
+    }
+}
+
+/// Downloads the artwork to the cache, named after its url so a notification that's still up
+/// never has its image swapped out from under it.
+fn fetch_artwork(url: &str) -> Result<PathBuf> {
+    let directory = dirs::cache_dir()
+        .ok_or_else(|| eyre!("couldn't find a cache directory"))?
+        .join("frick-dmca")
+        .join("artwork");
+    fs::create_dir_all(&directory)?;
+    let mut hasher = DefaultHasher::new();
+    url.hash(&mut hasher);
+    let path = directory.join(format!("{:x}", hasher.finish()));
+    if path.exists() {
+        return Ok(path);
+    }
+
+    let artwork = reqwest::blocking::get(url)?.error_for_status()?.bytes()?;
+    // So the notification server never reads half a file.
+    download::write_atomically(&path, &artwork)?;
+    Ok(path)
+}
@@ -0,0 +1,213 @@
+use crate::Asset;
+use color_eyre::eyre::{eyre, Result};
+use flate2::{write::GzEncoder, Compression};
+use rand::{distributions::Alphanumeric, Rng};
+use reqwest::{blocking::Client, redirect, Url};
+use std::{collections::HashMap, io::Write, net::SocketAddr, thread};
+use tiny_http::{Header, Method, Request, Response};
+
+/// Hosts the proxy forwards requests to, subdomains included.
+const ALLOWED_HOSTS: &[&str] = &["soundcloud.com", "sndcdn.com"];
+/// Response headers passed back to the page, everything else (cookies especially) is dropped.
+const FORWARDED_HEADERS: &[&str] = &["content-type", "content-range", "accept-ranges"];
+
+/// A loopback HTTP server that serves the embedded app and proxies requests to hosts without CORS headers.
+///
+/// The app's files are served separately, so the browser can compile the wasm while it's still downloading.
+///
+/// Every path is prefixed with a per-session token so other local processes can't use it.
+pub struct Server {
+    address: SocketAddr,
+    token: String,
+}
+
+impl Server {
+    pub fn start() -> Result<Self> {
+        let server = tiny_http::Server::http("127.0.0.1:0").map_err(|e| eyre!(e))?;
+        let address = server.server_addr();
+        let token = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(32)
+            .map(char::from)
+            .collect::<String>();
+        let client = Client::builder()
+            .redirect(redirect::Policy::custom(|attempt| {
+                if is_allowed(attempt.url()) {
+                    attempt.follow()
+                } else {
+                    attempt.stop()
+                }
+            }))
+            .build()?;
+
+        let proxy = Self { address, token };
+        let prefix = proxy.prefix();
+        let proxy_url = proxy.proxy_url();
+        thread::spawn(move || {
+            let mut compressed = HashMap::new();
+            for request in server.incoming_requests() {
+                if let Err(e) = serve(request, &prefix, &proxy_url, &client, &mut compressed) {
+                    eprintln!("failed to serve request: {}", e);
+                }
+            }
+        });
+
+        Ok(proxy)
+    }
+
+    fn prefix(&self) -> String {
+        format!("/{}/", self.token)
+    }
+
+    /// Base url the page prepends to upstream urls, in the same style as `https://warp-co.rs`.
+    pub fn proxy_url(&self) -> String {
+        format!("http://{}{}proxy", self.address, self.prefix())
+    }
+
+    /// The page's url, with `query` passed on to the app.
+    pub fn index_url(&self, query: &[(&str, &str)]) -> String {
+        let mut url = Url::parse(&format!(
+            "http://{}{}index.html",
+            self.address,
+            self.prefix()
+        ))
+        .expect("the index url is valid");
+        if !query.is_empty() {
+            url.query_pairs_mut().extend_pairs(query);
+        }
+        url.to_string()
+    }
+}
+
+fn is_allowed(url: &Url) -> bool {
+    url.scheme() == "https"
+        && url.host_str().map_or(false, |host| {
+            ALLOWED_HOSTS
+                .iter()
+                .any(|allowed| host == *allowed || host.ends_with(&format!(".{}", allowed)))
+        })
+}
+
+/// `compressed` keeps the gzipped assets around, so reloading the page doesn't compress them again.
+fn serve(
+    request: Request,
+    prefix: &str,
+    proxy_url: &str,
+    client: &Client,
+    compressed: &mut HashMap<String, Vec<u8>>,
+) -> Result<()> {
+    let path = match request.url().strip_prefix(prefix).map(str::to_owned) {
+        Some(path) => path,
+        None => return Ok(request.respond(Response::from_string("").with_status_code(404))?),
+    };
+
+    if let Some(upstream) = path.strip_prefix("proxy/") {
+        return Ok(match forward(&request, upstream, client) {
+            Ok(response) => request.respond(response)?,
+            Err(e) => {
+                request.respond(Response::from_string(e.to_string()).with_status_code(502))?
+            }
+        });
+    }
+
+    let path = path.split('?').next().unwrap_or_default();
+    let (body, mime_type) = match Asset::get(path) {
+        Some(content) if path == "index.html" => {
+            // The page looks the proxy up from this tag, CSP doesn't apply to `meta` elements.
+            let html = std::str::from_utf8(content.as_ref())?.replacen(
+                "<head>",
+                &format!(r#"<head><meta name="frick-proxy" content="{}">"#, proxy_url),
+                1,
+            );
+            (html.into_bytes(), "text/html; charset=utf-8")
+        }
+        // `WebAssembly.instantiateStreaming` only accepts `application/wasm`.
+        Some(content) => (
+            content.into_owned(),
+            mime_guess::from_path(path)
+                .first_raw()
+                .unwrap_or("application/octet-stream"),
+        ),
+        None => return Ok(request.respond(Response::from_string("").with_status_code(404))?),
+    };
+
+    let response = if is_compressible(mime_type) && accepts_gzip(&request) {
+        let body = match compressed.get(path) {
+            Some(body) => body.clone(),
+            None => {
+                let body = gzip(&body)?;
+                compressed.insert(path.to_owned(), body.clone());
+                body
+            }
+        };
+        Response::from_data(body)
+            .with_header(Header::from_bytes("Content-Encoding", "gzip").unwrap())
+    } else {
+        Response::from_data(body)
+    };
+    Ok(request.respond(response.with_header(content_type(mime_type)))?)
+}
+
+/// Images and audio are compressed already, text and wasm shrink to a fraction of their size.
+fn is_compressible(mime_type: &str) -> bool {
+    mime_type.starts_with("text/")
+        || matches!(
+            mime_type,
+            "application/wasm" | "application/javascript" | "application/json" | "image/svg+xml"
+        )
+}
+
+fn accepts_gzip(request: &Request) -> bool {
+    request.headers().iter().any(|header| {
+        header.field.equiv("accept-encoding")
+            && header
+                .value
+                .as_str()
+                .split(',')
+                .any(|coding| coding.split(';').next().map(str::trim) == Some("gzip"))
+    })
+}
+
+fn gzip(data: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+    Ok(encoder.finish()?)
+}
+
+fn forward(
+    request: &Request,
+    upstream: &str,
+    client: &Client,
+) -> Result<Response<std::io::Cursor<Vec<u8>>>> {
+    let url = Url::parse(upstream)?;
+    if *request.method() != Method::Get || !is_allowed(&url) {
+        return Ok(Response::from_string("").with_status_code(403));
+    }
+
+    let mut builder = client.get(url);
+    // Only headers that matter for media are forwarded, cookies never are.
+    for header in request.headers() {
+        if header.field.equiv("range") || header.field.equiv("accept") {
+            builder = builder.header(header.field.as_str().as_str(), header.value.as_str());
+        }
+    }
+    let upstream_response = builder.send()?;
+
+    let status = upstream_response.status().as_u16();
+    let headers = upstream_response
+        .headers()
+        .iter()
+        .filter(|(name, _)| FORWARDED_HEADERS.contains(&name.as_str()))
+        .filter_map(|(name, value)| Header::from_bytes(name.as_str(), value.as_bytes()).ok())
+        .collect::<Vec<_>>();
+    let mut response =
+        Response::from_data(upstream_response.bytes()?.to_vec()).with_status_code(status);
+    for header in headers {
+        response.add_header(header);
+    }
+    Ok(response)
+}
+
+fn content_type(value: &str) -> Header {
+    Header::from_bytes("Content-Type", value).unwrap()
+}
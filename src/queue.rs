@@ -0,0 +1,694 @@
+//! Downloads queued by the page, kept on disk so they survive restarts.
+
+use crate::{download, hls, invoke, remux, resolve, State};
+use bridge::{Download, DownloadState, Event, HostMessage, Metadata};
+use color_eyre::eyre::{eyre, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+use web_view::Handle;
+
+const DEFAULT_PARALLELISM: usize = 3;
+/// How many times a download is tried before it's marked as failed.
+const MAX_ATTEMPTS: u32 = 5;
+/// The delay before the first retry, doubled on every one after it.
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Fetches a job's song into a file, swapped out in tests so they don't need the network.
+pub trait Downloader: Send + Sync {
+    /// Downloads `quality` of the song at `metadata.source_url` to `path`, returning where it ended up.
+    fn download(
+        &self,
+        metadata: &Metadata,
+        quality: &str,
+        mime_type: &str,
+        path: &Path,
+        progress: &mut dyn FnMut(u64, Option<u64>) -> bool,
+    ) -> Result<PathBuf>;
+}
+
+/// Resolves the song again before every attempt, since stream urls are signed and expire
+/// long before a queue that's been paused for a while gets to them.
+struct Streams;
+
+impl Downloader for Streams {
+    fn download(
+        &self,
+        metadata: &Metadata,
+        quality: &str,
+        mime_type: &str,
+        path: &Path,
+        progress: &mut dyn FnMut(u64, Option<u64>) -> bool,
+    ) -> Result<PathBuf> {
+        let song = resolve::song(&metadata.source_url)?;
+        let (is_hls, mime_type, url) = resolve::stream(&song, quality, mime_type)
+            .ok_or_else(|| eyre!("{} no longer has a {} stream", song.title(), quality))?;
+        if *is_hls {
+            hls::download(url, path, progress)
+        } else {
+            download::download(url, path, progress)
+        }
+        .and_then(|path| remux::finish(&path, mime_type, metadata))
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Job {
+    id: u32,
+    /// A key of the song's streams, missing from jobs queued before they were resolved for every attempt.
+    #[serde(default)]
+    quality: String,
+    mime_type: String,
+    metadata: Metadata,
+    directory: PathBuf,
+    /// Picked when the job is queued so it doesn't end up in another one's file,
+    /// missing from jobs queued before it was.
+    #[serde(default)]
+    file_name: String,
+    state: DownloadState,
+    downloaded: u64,
+    total: Option<u64>,
+    attempts: u32,
+}
+
+impl Job {
+    fn path(&self) -> PathBuf {
+        self.directory.join(&self.file_name)
+    }
+
+    fn remove_partial_download(&self) {
+        let _ = fs::remove_file(download::part_path(&self.path()));
+        let _ = fs::remove_file(hls::progress_path(&self.path()));
+    }
+
+    fn to_download(&self) -> Download {
+        Download {
+            id: self.id,
+            title: self.metadata.title.clone(),
+            state: self.state.clone(),
+            downloaded: self.downloaded,
+            total: self.total,
+        }
+    }
+}
+
+/// What a running job is asked to do instead of finishing.
+enum Stop {
+    Pause,
+    Cancel,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Inner {
+    parallelism: usize,
+    jobs: BTreeMap<u32, Job>,
+    /// Never goes back down, so a removed job's id doesn't get reused while the page still knows it.
+    #[serde(default)]
+    next_id: u32,
+    #[serde(skip)]
+    stopping: HashMap<u32, Stop>,
+    /// Where events go once there's a page to report to.
+    #[serde(skip)]
+    events: Option<Box<dyn Fn(Event) + Send>>,
+}
+
+#[derive(Clone)]
+pub struct Queue {
+    inner: Arc<Mutex<Inner>>,
+    path: PathBuf,
+    downloader: Arc<dyn Downloader>,
+    retry_delay: Duration,
+}
+
+impl Queue {
+    /// Loads the jobs saved by a previous run, queueing the ones that were interrupted by it.
+    pub fn load() -> Result<Self> {
+        let path = dirs::data_dir()
+            .ok_or_else(|| eyre!("couldn't find a data directory"))?
+            .join("frick-dmca")
+            .join("downloads.json");
+        Ok(Self::open(path, Arc::new(Streams), RETRY_DELAY))
+    }
+
+    fn open(path: PathBuf, downloader: Arc<dyn Downloader>, retry_delay: Duration) -> Self {
+        let mut inner = match fs::read(&path) {
+            Ok(json) => serde_json::from_slice(&json).unwrap_or_else(|e| {
+                eprintln!("ignoring corrupt download list {}: {}", path.display(), e);
+                Inner::default()
+            }),
+            Err(_) => Inner::default(),
+        };
+        // Lists saved before `next_id` was have it missing.
+        if let Some(last) = inner.jobs.keys().next_back() {
+            inner.next_id = inner.next_id.max(last + 1);
+        }
+        for job in inner.jobs.values_mut() {
+            if job.file_name.is_empty() {
+                job.file_name = download::file_name(&job.metadata.title, &job.mime_type);
+            }
+            if matches!(
+                job.state,
+                DownloadState::Running | DownloadState::Retrying { .. }
+            ) {
+                job.state = DownloadState::Queued;
+            }
+        }
+        Self {
+            inner: Arc::new(Mutex::new(inner)),
+            path,
+            downloader,
+            retry_delay,
+        }
+    }
+
+    /// Starts running queued jobs, reporting their progress through `handle`.
+    pub fn attach(&self, handle: Handle<State>) {
+        self.attach_events(move |event| {
+            if let Err(e) = invoke::post(&handle, &HostMessage::Event { event }) {
+                eprintln!("failed to send event to the page: {}", e);
+            }
+        });
+    }
+
+    fn attach_events(&self, events: impl Fn(Event) + Send + 'static) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.events = Some(Box::new(events));
+        self.schedule(&mut inner);
+    }
+
+    pub fn add(
+        &self,
+        quality: String,
+        mime_type: String,
+        metadata: Metadata,
+        directory: PathBuf,
+    ) -> u32 {
+        let mut inner = self.inner.lock().unwrap();
+        let id = inner.next_id;
+        inner.next_id += 1;
+        let path = directory.join(download::file_name(&metadata.title, &mime_type));
+        // Files other jobs are going to write count too, even while they're only queued.
+        let path = download::unique_path(&path, |candidate| {
+            download::part_path(candidate).exists()
+                || inner.jobs.values().any(|job| job.path() == candidate)
+        });
+        let file_name = path.file_name().unwrap().to_string_lossy().into_owned();
+        inner.jobs.insert(
+            id,
+            Job {
+                id,
+                quality,
+                mime_type,
+                metadata,
+                directory,
+                file_name,
+                state: DownloadState::Queued,
+                downloaded: 0,
+                total: None,
+                attempts: 0,
+            },
+        );
+        self.changed(&mut inner, id);
+        id
+    }
+
+    pub fn list(&self) -> (Vec<Download>, usize) {
+        let inner = self.inner.lock().unwrap();
+        let downloads = inner.jobs.values().map(Job::to_download).collect();
+        (downloads, inner.parallelism)
+    }
+
+    pub fn pause(&self, id: u32) {
+        self.stop(id, Stop::Pause, DownloadState::Paused);
+    }
+
+    pub fn cancel(&self, id: u32) {
+        self.stop(id, Stop::Cancel, DownloadState::Cancelled);
+    }
+
+    fn stop(&self, id: u32, stop: Stop, state: DownloadState) {
+        let mut inner = self.inner.lock().unwrap();
+        let job = match inner.jobs.get_mut(&id) {
+            Some(job) => job,
+            None => return,
+        };
+        match job.state {
+            // The worker notices on its next progress report.
+            DownloadState::Running => {
+                inner.stopping.insert(id, stop);
+            }
+            DownloadState::Queued | DownloadState::Retrying { .. } | DownloadState::Paused => {
+                if state == DownloadState::Cancelled {
+                    job.remove_partial_download();
+                }
+                job.state = state;
+                self.changed(&mut inner, id);
+            }
+            _ => {}
+        }
+    }
+
+    /// Queues a paused, failed or cancelled job again.
+    pub fn resume(&self, id: u32) {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(job) = inner.jobs.get_mut(&id) {
+            if matches!(
+                job.state,
+                DownloadState::Paused | DownloadState::Failed { .. } | DownloadState::Cancelled
+            ) {
+                job.state = DownloadState::Queued;
+                job.attempts = 0;
+                self.changed(&mut inner, id);
+            }
+        }
+    }
+
+    /// Forgets about a job that isn't running, throwing away whatever it downloaded unless it finished.
+    pub fn remove(&self, id: u32) {
+        let mut inner = self.inner.lock().unwrap();
+        match inner.jobs.get(&id) {
+            Some(job) if job.state != DownloadState::Running => {
+                if !matches!(job.state, DownloadState::Finished { .. }) {
+                    job.remove_partial_download();
+                }
+                inner.jobs.remove(&id);
+                self.save(&inner);
+                self.send(&inner, Event::DownloadRemoved { id });
+            }
+            _ => {}
+        }
+    }
+
+    pub fn set_parallelism(&self, parallelism: usize) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.parallelism = parallelism.max(1);
+        self.save(&inner);
+        self.schedule(&mut inner);
+    }
+
+    /// Persists and reports a change to a job, then starts whatever can run now.
+    fn changed(&self, inner: &mut Inner, id: u32) {
+        self.save(inner);
+        self.notify(inner, id);
+        self.schedule(inner);
+    }
+
+    fn notify(&self, inner: &Inner, id: u32) {
+        if let Some(job) = inner.jobs.get(&id) {
+            self.send(
+                inner,
+                Event::DownloadUpdated {
+                    download: job.to_download(),
+                },
+            );
+        }
+    }
+
+    fn schedule(&self, inner: &mut Inner) {
+        // Nothing runs until there's a page to report to.
+        if inner.events.is_none() {
+            return;
+        }
+        let running = inner
+            .jobs
+            .values()
+            .filter(|job| job.state == DownloadState::Running)
+            .count();
+        let ready = inner
+            .jobs
+            .values()
+            .filter(|job| job.state == DownloadState::Queued)
+            .map(|job| job.id)
+            .take(inner.parallelism.saturating_sub(running))
+            .collect::<Vec<_>>();
+        for &id in &ready {
+            let job = inner.jobs.get_mut(&id).unwrap();
+            job.state = DownloadState::Running;
+            let job = job.clone();
+            self.notify(inner, id);
+            let queue = self.clone();
+            thread::spawn(move || queue.run(job));
+        }
+        if !ready.is_empty() {
+            self.save(inner);
+        }
+    }
+
+    fn run(&self, job: Job) {
+        let id = job.id;
+        let mut progress = |downloaded, total| {
+            let mut inner = self.inner.lock().unwrap();
+            if let Some(job) = inner.jobs.get_mut(&id) {
+                job.downloaded = downloaded;
+                job.total = total;
+            }
+            self.notify(&inner, id);
+            !inner.stopping.contains_key(&id)
+        };
+        let result = self.downloader.download(
+            &job.metadata,
+            &job.quality,
+            &job.mime_type,
+            &job.path(),
+            &mut progress,
+        );
+
+        let mut inner = self.inner.lock().unwrap();
+        let stop = inner.stopping.remove(&id);
+        let job = match inner.jobs.get_mut(&id) {
+            Some(job) => job,
+            None => return,
+        };
+        job.state = match (result, stop) {
+            (Ok(path), _) => DownloadState::Finished {
+                path: path.display().to_string(),
+            },
+            (Err(_), Some(Stop::Pause)) => DownloadState::Paused,
+            (Err(_), Some(Stop::Cancel)) => {
+                job.remove_partial_download();
+                DownloadState::Cancelled
+            }
+            (Err(e), None) => {
+                job.attempts += 1;
+                if job.attempts < MAX_ATTEMPTS {
+                    let delay = self.retry_delay * 2u32.pow(job.attempts - 1);
+                    self.retry_later(id, delay);
+                    DownloadState::Retrying {
+                        attempt: job.attempts,
+                        delay_secs: delay.as_secs(),
+                    }
+                } else {
+                    DownloadState::Failed {
+                        message: e.to_string(),
+                    }
+                }
+            }
+        };
+        self.changed(&mut inner, id);
+    }
+
+    fn retry_later(&self, id: u32, delay: Duration) {
+        let queue = self.clone();
+        thread::spawn(move || {
+            thread::sleep(delay);
+            let mut inner = queue.inner.lock().unwrap();
+            // The job might have been paused or cancelled in the meantime.
+            match inner.jobs.get_mut(&id) {
+                Some(job) if matches!(job.state, DownloadState::Retrying { .. }) => {
+                    job.state = DownloadState::Queued;
+                    queue.changed(&mut inner, id);
+                }
+                _ => {}
+            }
+        });
+    }
+
+    fn save(&self, inner: &Inner) {
+        if let Err(e) = write_json(&self.path, inner) {
+            eprintln!("failed to save {}: {}", self.path.display(), e);
+        }
+    }
+
+    fn send(&self, inner: &Inner, event: Event) {
+        if let Some(events) = &inner.events {
+            events(event);
+        }
+    }
+}
+
+fn write_json(path: &Path, inner: &Inner) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // A crash halfway through would otherwise lose every download.
+    download::write_atomically(path, serde_json::to_vec_pretty(inner)?)
+}
+
+impl Default for Inner {
+    fn default() -> Self {
+        Self {
+            parallelism: DEFAULT_PARALLELISM,
+            jobs: BTreeMap::new(),
+            next_id: 0,
+            stopping: HashMap::new(),
+            events: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, sync::mpsc, time::Instant};
+
+    /// Reports `chunks` chunks of progress a few milliseconds apart, after failing `failures` attempts.
+    struct Stub {
+        chunks: u64,
+        failures: Mutex<u32>,
+        attempts: Mutex<Vec<Instant>>,
+    }
+
+    impl Stub {
+        fn new(chunks: u64, failures: u32) -> Arc<Self> {
+            Arc::new(Self {
+                chunks,
+                failures: Mutex::new(failures),
+                attempts: Mutex::new(Vec::new()),
+            })
+        }
+    }
+
+    impl Downloader for Stub {
+        fn download(
+            &self,
+            _metadata: &Metadata,
+            _quality: &str,
+            _mime_type: &str,
+            path: &Path,
+            progress: &mut dyn FnMut(u64, Option<u64>) -> bool,
+        ) -> Result<PathBuf> {
+            self.attempts.lock().unwrap().push(Instant::now());
+            fs::write(download::part_path(path), "")?;
+            {
+                let mut failures = self.failures.lock().unwrap();
+                if *failures > 0 {
+                    *failures -= 1;
+                    return Err(eyre!("stub failure"));
+                }
+            }
+            for chunk in 0..=self.chunks {
+                if !progress(chunk, Some(self.chunks)) {
+                    return Err(eyre!("download interrupted"));
+                }
+                thread::sleep(Duration::from_millis(10));
+            }
+            fs::rename(download::part_path(path), path)?;
+            Ok(path.to_owned())
+        }
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!(
+            "frick-dmca-queue-{}-{}",
+            name,
+            rand::random::<u32>()
+        ))
+    }
+
+    /// A queue that's reporting to the returned receiver.
+    fn queue(path: PathBuf, stub: &Arc<Stub>) -> (Queue, mpsc::Receiver<Event>) {
+        let downloader = Arc::clone(stub) as Arc<dyn Downloader>;
+        let queue = Queue::open(path, downloader, Duration::from_millis(50));
+        let (sender, receiver) = mpsc::channel();
+        queue.attach_events(move |event| {
+            let _ = sender.send(event);
+        });
+        (queue, receiver)
+    }
+
+    fn add(queue: &Queue, title: &str) -> u32 {
+        let metadata = Metadata {
+            title: title.to_owned(),
+            artist: None,
+            source_url: "https://example.com/song".to_owned(),
+            artwork_url: None,
+        };
+        queue.add(
+            "high".to_owned(),
+            "audio/mpeg".to_owned(),
+            metadata,
+            env::temp_dir(),
+        )
+    }
+
+    /// Waits for the next update that `matches`, skipping every other one.
+    fn wait_for(
+        events: &mpsc::Receiver<Event>,
+        matches: impl Fn(&DownloadState) -> bool,
+    ) -> DownloadState {
+        loop {
+            match events.recv_timeout(Duration::from_secs(5)).unwrap() {
+                Event::DownloadUpdated { download } if matches(&download.state) => {
+                    return download.state
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn job_path(queue: &Queue, id: u32) -> PathBuf {
+        queue.inner.lock().unwrap().jobs[&id].path()
+    }
+
+    #[test]
+    fn pauses_and_resumes() {
+        let stub = Stub::new(100, 0);
+        let list = temp_path("pause.json");
+        let (queue, events) = queue(list.clone(), &stub);
+        let id = add(&queue, &format!("pause {}", rand::random::<u32>()));
+        let path = job_path(&queue, id);
+
+        wait_for(&events, |state| *state == DownloadState::Running);
+        queue.pause(id);
+        wait_for(&events, |state| *state == DownloadState::Paused);
+        let paused_with_partial_download = download::part_path(&path).exists();
+        queue.resume(id);
+        let finished = wait_for(&events, |state| state.is_done());
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(&list);
+
+        assert!(paused_with_partial_download);
+        assert_eq!(
+            finished,
+            DownloadState::Finished {
+                path: path.display().to_string()
+            }
+        );
+        assert_eq!(stub.attempts.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn cancels_and_removes_the_partial_download() {
+        let stub = Stub::new(100, 0);
+        let list = temp_path("cancel.json");
+        let (queue, events) = queue(list.clone(), &stub);
+        let id = add(&queue, &format!("cancel {}", rand::random::<u32>()));
+        let path = job_path(&queue, id);
+
+        wait_for(&events, |state| *state == DownloadState::Running);
+        queue.cancel(id);
+        let cancelled = wait_for(&events, |state| state.is_done());
+        let _ = fs::remove_file(&list);
+
+        assert_eq!(cancelled, DownloadState::Cancelled);
+        assert!(!download::part_path(&path).exists());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn retries_with_a_growing_delay() {
+        let stub = Stub::new(0, 2);
+        let list = temp_path("retry.json");
+        let (queue, events) = queue(list.clone(), &stub);
+        let id = add(&queue, &format!("retry {}", rand::random::<u32>()));
+        let path = job_path(&queue, id);
+
+        let first = wait_for(&events, |state| {
+            matches!(state, DownloadState::Retrying { .. })
+        });
+        let second = wait_for(&events, |state| {
+            matches!(state, DownloadState::Retrying { .. })
+        });
+        let finished = wait_for(&events, |state| state.is_done());
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(&list);
+
+        assert_eq!(
+            first,
+            DownloadState::Retrying {
+                attempt: 1,
+                delay_secs: 0,
+            }
+        );
+        assert_eq!(
+            second,
+            DownloadState::Retrying {
+                attempt: 2,
+                delay_secs: 0,
+            }
+        );
+        assert!(matches!(finished, DownloadState::Finished { .. }));
+        let attempts = stub.attempts.lock().unwrap();
+        assert_eq!(attempts.len(), 3);
+        assert!(attempts[1] - attempts[0] >= Duration::from_millis(50));
+        assert!(attempts[2] - attempts[1] >= Duration::from_millis(100));
+    }
+
+    #[test]
+    fn fails_after_the_last_attempt() {
+        let stub = Stub::new(0, MAX_ATTEMPTS);
+        let list = temp_path("fail.json");
+        let (queue, events) = queue(list.clone(), &stub);
+        let id = add(&queue, &format!("fail {}", rand::random::<u32>()));
+        let path = job_path(&queue, id);
+
+        let failed = wait_for(&events, |state| state.is_done());
+        let _ = fs::remove_file(download::part_path(&path));
+        let _ = fs::remove_file(&list);
+
+        assert_eq!(
+            failed,
+            DownloadState::Failed {
+                message: "stub failure".to_owned()
+            }
+        );
+        assert_eq!(stub.attempts.lock().unwrap().len(), MAX_ATTEMPTS as usize);
+    }
+
+    #[test]
+    fn picks_a_free_file_name() {
+        let stub = Stub::new(0, 0);
+        let list = temp_path("names.json");
+        let queue = Queue::open(list.clone(), stub, RETRY_DELAY);
+        let title = format!("names {}", rand::random::<u32>());
+        let existing = env::temp_dir().join(format!("{}.mp3", title));
+        fs::write(&existing, "").unwrap();
+        let first = add(&queue, &title);
+        let second = add(&queue, &title);
+        let paths = (job_path(&queue, first), job_path(&queue, second));
+        let _ = fs::remove_file(&existing);
+        let _ = fs::remove_file(&list);
+
+        assert_eq!(
+            paths,
+            (
+                env::temp_dir().join(format!("{} (2).mp3", title)),
+                env::temp_dir().join(format!("{} (3).mp3", title)),
+            )
+        );
+    }
+
+    #[test]
+    fn never_reuses_ids() {
+        let stub = Stub::new(0, 0);
+        let list = temp_path("ids.json");
+        // Without a page to report to nothing runs, so the jobs can be removed right away.
+        let queue = Queue::open(list.clone(), stub.clone(), RETRY_DELAY);
+        let first = add(&queue, "first");
+        let second = add(&queue, "second");
+        queue.remove(first);
+        queue.remove(second);
+        let reopened = Queue::open(list.clone(), stub, RETRY_DELAY);
+        let third = add(&reopened, "third");
+        reopened.remove(third);
+        let _ = fs::remove_file(&list);
+
+        assert_eq!((first, second, third), (0, 1, 2));
+    }
+}
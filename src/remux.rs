@@ -0,0 +1,211 @@
+//! Turns downloaded streams into tagged audio files by running them through `ffmpeg` without re-encoding.
+
+use crate::download;
+use bridge::Metadata;
+use color_eyre::eyre::{eyre, Result};
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// Picks the container a stream's codec is best kept in, if it's one we know.
+fn target_extension(mime_type: &str) -> Option<&'static str> {
+    let mime_type = mime_type.to_lowercase();
+    if mime_type.contains("opus") {
+        Some("opus")
+    } else if mime_type.starts_with("audio/mpeg") {
+        Some("mp3")
+    } else if mime_type.starts_with("audio/mp4") || mime_type.contains("mp4a") {
+        Some("m4a")
+    } else {
+        None
+    }
+}
+
+fn image_mime_type(data: &[u8]) -> Option<&'static str> {
+    if data.starts_with(&[0xff, 0xd8, 0xff]) {
+        Some("image/jpeg")
+    } else if data.starts_with(b"\x89PNG") {
+        Some("image/png")
+    } else {
+        None
+    }
+}
+
+/// Escapes a value for an `ffmetadata` file.
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '=' | ';' | '#' | '\\' | '\n') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// A FLAC picture block, which is how Ogg files carry artwork in their Vorbis comments.
+fn picture_block(data: &[u8], mime_type: &str) -> Vec<u8> {
+    let mut block = Vec::with_capacity(data.len() + mime_type.len() + 32);
+    // Front cover.
+    block.extend_from_slice(&3u32.to_be_bytes());
+    block.extend_from_slice(&(mime_type.len() as u32).to_be_bytes());
+    block.extend_from_slice(mime_type.as_bytes());
+    // No description, and unknown width, height, color depth and palette size.
+    for _ in 0..5 {
+        block.extend_from_slice(&0u32.to_be_bytes());
+    }
+    block.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    block.extend_from_slice(data);
+    block
+}
+
+fn fetch_artwork(url: &str) -> Result<(Vec<u8>, &'static str)> {
+    let data = reqwest::blocking::get(url)?
+        .error_for_status()?
+        .bytes()?
+        .to_vec();
+    let mime_type = image_mime_type(&data).ok_or_else(|| eyre!("unknown image format"))?;
+    Ok((data, mime_type))
+}
+
+/// Remuxes the file at `path` into the container that suits its codec and tags it with `metadata`.
+///
+/// Returns the path of the resulting file, which is the original one if `ffmpeg` isn't installed or fails.
+pub fn finish(path: &Path, mime_type: &str, metadata: &Metadata) -> Result<PathBuf> {
+    let extension = match target_extension(mime_type) {
+        Some(extension) => extension,
+        None => return Ok(path.to_owned()),
+    };
+    let output = path.with_extension(extension);
+    // ffmpeg can't write to the file it reads from.
+    let temporary_output = path.with_extension(format!("tagged.{}", extension));
+    let metadata_path = path.with_extension("ffmetadata");
+
+    let artwork = metadata.artwork_url.as_deref().and_then(|url| {
+        fetch_artwork(url)
+            .map_err(|e| eprintln!("not embedding artwork from {}: {}", url, e))
+            .ok()
+    });
+
+    let mut ffmetadata = format!(";FFMETADATA1\ntitle={}\n", escape(&metadata.title));
+    if let Some(artist) = &metadata.artist {
+        ffmetadata.push_str(&format!("artist={}\n", escape(artist)));
+    }
+    ffmetadata.push_str(&format!("comment={}\n", escape(&metadata.source_url)));
+    let mut artwork_path = None;
+    match &artwork {
+        // Ogg has no attached pictures, the artwork goes in a Vorbis comment instead.
+        Some((data, mime_type)) if extension == "opus" => ffmetadata.push_str(&format!(
+            "METADATA_BLOCK_PICTURE={}\n",
+            escape(&base64::encode(picture_block(data, mime_type)))
+        )),
+        Some((data, mime_type)) => {
+            let image_extension = if *mime_type == "image/png" {
+                "cover.png"
+            } else {
+                "cover.jpg"
+            };
+            let path = path.with_extension(image_extension);
+            match fs::write(&path, data) {
+                Ok(()) => artwork_path = Some(path),
+                Err(e) => eprintln!(
+                    "not embedding artwork, writing {} failed: {}",
+                    path.display(),
+                    e
+                ),
+            }
+        }
+        None => {}
+    }
+    fs::write(&metadata_path, ffmetadata)?;
+
+    let mut command = Command::new("ffmpeg");
+    command
+        .args(&["-y", "-loglevel", "error", "-i"])
+        .arg(path)
+        .args(&["-f", "ffmetadata", "-i"])
+        .arg(&metadata_path);
+    if let Some(artwork_path) = &artwork_path {
+        command.arg("-i").arg(artwork_path);
+    }
+    command.args(&["-map", "0:a", "-map_metadata", "1", "-c", "copy"]);
+    if artwork_path.is_some() {
+        command.args(&["-map", "2:v", "-disposition:v:0", "attached_pic"]);
+    }
+    if extension == "mp3" {
+        // The most widely supported ID3v2 version.
+        command.args(&["-id3v2_version", "3"]);
+    }
+    let status = command.arg(&temporary_output).status();
+
+    let _ = fs::remove_file(&metadata_path);
+    if let Some(artwork_path) = &artwork_path {
+        let _ = fs::remove_file(artwork_path);
+    }
+
+    match status {
+        Ok(status) if status.success() => {
+            fs::remove_file(path)?;
+            // Another song with the same title might already be there in this container.
+            let output = download::unique_path(&output, |_| false);
+            fs::rename(&temporary_output, &output)?;
+            Ok(output)
+        }
+        // The download itself is fine, so it's kept without tags rather than thrown away.
+        Ok(status) => {
+            let _ = fs::remove_file(&temporary_output);
+            eprintln!(
+                "ffmpeg exited with {}, keeping {} as is",
+                status,
+                path.display()
+            );
+            Ok(path.to_owned())
+        }
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            eprintln!("ffmpeg isn't installed, keeping {} as is", path.display());
+            Ok(path.to_owned())
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_ffmetadata_values() {
+        assert_eq!(escape("plain title"), "plain title");
+        assert_eq!(escape("a=b;c#d\\e\nf"), "a\\=b\\;c\\#d\\\\e\\\nf");
+    }
+
+    #[test]
+    fn picks_containers_by_codec() {
+        assert_eq!(target_extension("audio/ogg; codecs=\"opus\""), Some("opus"));
+        assert_eq!(
+            target_extension("audio/webm; codecs=\"opus\""),
+            Some("opus")
+        );
+        assert_eq!(target_extension("audio/mpeg"), Some("mp3"));
+        assert_eq!(
+            target_extension("audio/mp4; codecs=\"mp4a.40.2\""),
+            Some("m4a")
+        );
+        assert_eq!(target_extension("Audio/MP4"), Some("m4a"));
+        assert_eq!(target_extension("audio/webm; codecs=\"vorbis\""), None);
+    }
+
+    #[test]
+    fn builds_picture_blocks() {
+        let block = picture_block(b"\xff\xd8\xffdata", "image/jpeg");
+        let mut expected = vec![0, 0, 0, 3, 0, 0, 0, 10];
+        expected.extend_from_slice(b"image/jpeg");
+        expected.extend_from_slice(&[0; 20]);
+        expected.extend_from_slice(&[0, 0, 0, 7]);
+        expected.extend_from_slice(b"\xff\xd8\xffdata");
+        assert_eq!(block, expected);
+    }
+}
@@ -0,0 +1,48 @@
+//! Resolves songs natively, with the same providers and settings the page uses.
+
+use crate::settings;
+use color_eyre::eyre::{eyre, Result};
+use frick_providers::{
+    native::{FileStorage, ReqwestClient},
+    Song,
+};
+use std::rc::Rc;
+
+/// Resolves the song at `url`, getting fresh stream urls every time.
+pub fn song(url: &str) -> Result<Song> {
+    futures::executor::block_on(async {
+        let providers = frick_providers::all(
+            Rc::new(ReqwestClient::default()),
+            Rc::new(FileStorage::open_default()?),
+            &settings::load().providers,
+        )
+        .await?;
+        let provider = providers
+            .iter()
+            .find(|provider| provider.is_match(url))
+            .ok_or_else(|| eyre!("no provider supports {}", url))?;
+        provider.song_from_url(url).await
+    })
+}
+
+/// The stream of `song` in `quality`, preferring one in `mime_type`.
+///
+/// Falls back to any stream in `mime_type` if the quality is gone, which keeps downloads
+/// queued before their provider renamed its qualities working.
+pub fn stream<'a>(
+    song: &'a Song,
+    quality: &str,
+    mime_type: &str,
+) -> Option<&'a (bool, String, String)> {
+    match song.urls().get(quality) {
+        Some(streams) => streams
+            .iter()
+            .find(|(_, stream_mime_type, _)| stream_mime_type == mime_type)
+            .or_else(|| streams.first()),
+        None => song
+            .urls()
+            .values()
+            .flatten()
+            .find(|(_, stream_mime_type, _)| stream_mime_type == mime_type),
+    }
+}
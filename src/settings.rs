@@ -0,0 +1,61 @@
+//! The settings in `config.toml`, which the page edits over the bridge.
+
+use crate::download;
+use bridge::Settings;
+use color_eyre::eyre::{eyre, Result};
+use std::{fs, path::PathBuf};
+
+/// `frick-dmca/config.toml` in the user's config directory, `$XDG_CONFIG_HOME` on Linux.
+fn path() -> Result<PathBuf> {
+    Ok(dirs::config_dir()
+        .ok_or_else(|| eyre!("couldn't find a config directory"))?
+        .join("frick-dmca")
+        .join("config.toml"))
+}
+
+/// The saved settings, brought up to date, or the defaults when there aren't any.
+pub fn load() -> Settings {
+    let path = match path() {
+        Ok(path) => path,
+        Err(_) => return Settings::default(),
+    };
+    let settings = match fs::read_to_string(&path) {
+        Ok(toml) => toml::from_str::<Settings>(&toml).unwrap_or_else(|e| {
+            eprintln!("ignoring corrupt config {}: {}", path.display(), e);
+            Settings::default()
+        }),
+        Err(_) => Settings::default(),
+    };
+    if settings.is_from_newer_version() {
+        eprintln!(
+            "{} was written by a newer version, settings won't be saved",
+            path.display()
+        );
+    }
+    settings.migrate()
+}
+
+pub fn save(settings: &Settings) -> Result<()> {
+    if settings.is_from_newer_version() {
+        return Err(eyre!("not overwriting settings from a newer version"));
+    }
+    let path = path()?;
+    if let Some(directory) = path.parent() {
+        fs::create_dir_all(directory)?;
+    }
+    download::write_atomically(&path, toml::to_string(settings)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refuses_to_save_settings_from_a_newer_version() {
+        let settings = Settings {
+            version: bridge::SETTINGS_VERSION + 1,
+            ..Settings::default()
+        };
+        assert!(save(&settings).is_err());
+    }
+}
@@ -0,0 +1,54 @@
+//! The window's title and geometry, which is kept in `window.toml` between runs.
+
+use crate::download;
+use bridge::{Metadata, PlaybackStatus, WindowGeometry};
+use color_eyre::eyre::{eyre, Result};
+use std::{fs, path::PathBuf};
+
+pub const DEFAULT_TITLE: &str = "frick-dmca";
+
+fn path() -> Result<PathBuf> {
+    Ok(dirs::config_dir()
+        .ok_or_else(|| eyre!("couldn't find a config directory"))?
+        .join("frick-dmca")
+        .join("window.toml"))
+}
+
+/// The geometry saved by the previous run, or the default one for the first.
+pub fn load() -> WindowGeometry {
+    let path = match path() {
+        Ok(path) => path,
+        Err(_) => return WindowGeometry::default(),
+    };
+    match fs::read_to_string(&path) {
+        Ok(toml) => toml::from_str(&toml).unwrap_or_else(|e| {
+            eprintln!("ignoring corrupt window state {}: {}", path.display(), e);
+            WindowGeometry::default()
+        }),
+        Err(_) => WindowGeometry::default(),
+    }
+}
+
+pub fn save(geometry: &WindowGeometry) -> Result<()> {
+    let path = path()?;
+    if let Some(directory) = path.parent() {
+        fs::create_dir_all(directory)?;
+    }
+    download::write_atomically(&path, toml::to_string(geometry)?)
+}
+
+/// Like `▶ Artist - Title`, or just the app's name when nothing is loaded.
+pub fn title(song: Option<&Metadata>, status: PlaybackStatus) -> String {
+    let song = match song {
+        Some(song) => song,
+        None => return DEFAULT_TITLE.to_owned(),
+    };
+    let symbol = match status {
+        PlaybackStatus::Playing => "▶",
+        PlaybackStatus::Paused | PlaybackStatus::Stopped => "⏸",
+    };
+    match &song.artist {
+        Some(artist) => format!("{} {} - {}", symbol, artist, song.title),
+        None => format!("{} {}", symbol, song.title),
+    }
+}
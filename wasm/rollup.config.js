@@ -24,7 +24,7 @@ export default {
         // assetFileNames: "[name]-[hash][extname]"
     },
     watch: {
-        include: ["src/**/*.rs", "Cargo.toml", "css/**/*.css", "js/**/*.js"]
+        include: ["src/**/*.rs", "Cargo.toml", "../bridge/src/**/*.rs", "../providers/src/**/*.rs", "css/**/*.css", "js/**/*.js"]
     },
     plugins: [
         commonjs(),
@@ -50,11 +50,23 @@ export default {
                     "https://warp-co.rs/https://soundcloud.com",
                     "https://a-v2.sndcdn.com/assets/*"
                 ],
+                // The CORS proxy is configurable at runtime, so it can be any https or loopback host.
+                "connect-src": [
+                    "'self'",
+                    "https:",
+                    "http://127.0.0.1:*",
+                    "http://localhost:*"
+                ],
                 "media-src": "https://*.googlevideo.com",
+                // The scripts and styles are separate files served next to the page.
+                "script-src": ["'self'"],
+                "style-src": ["'self'"],
             }
         }),
         rust({
             // cargoArgs: production && [] || ["--features", "debug"]
+            // Relative to the page, since the desktop app serves it under a per-session prefix.
+            serverPath: "",
         }),
         styles({
             include: ["css/**/*.css"],
@@ -0,0 +1,133 @@
+//! Functions exported to JavaScript, for pages that want the resolvers without the app.
+//!
+//! Everything returned is a plain object whose shape only changes in backwards compatible ways.
+
+use crate::providers::{self, Provider, Song};
+use seed::prelude::*;
+use serde::Serialize;
+use simple_eyre::eyre::{eyre, Report, Result};
+use std::rc::Rc;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SongJs {
+    title: String,
+    artist: Option<String>,
+    source_url: String,
+    artwork_url: Option<String>,
+    start_time: Option<f64>,
+    /// Seconds since the epoch, after which the stream urls have to be resolved again.
+    expires_at: Option<f64>,
+    streams: Vec<StreamJs>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct StreamJs {
+    quality: String,
+    /// Like "Opus 160 kbps".
+    label: String,
+    codec: Option<String>,
+    /// In kbps.
+    bitrate: Option<u32>,
+    is_hls: bool,
+    mime_type: String,
+    url: String,
+}
+
+impl From<Song> for SongJs {
+    fn from(song: Song) -> Self {
+        let mut streams = song
+            .urls()
+            .iter()
+            .flat_map(|(quality, streams)| {
+                let details = song.qualities().get(quality);
+                streams
+                    .iter()
+                    .map(move |(is_hls, mime_type, url)| StreamJs {
+                        quality: quality.clone(),
+                        label: details.map_or_else(|| quality.clone(), |details| details.label()),
+                        codec: details.map(|details| details.codec.clone()),
+                        bitrate: details.and_then(|details| details.bitrate),
+                        is_hls: *is_hls,
+                        mime_type: mime_type.clone(),
+                        url: url.clone(),
+                    })
+            })
+            .collect::<Vec<_>>();
+        // The urls are kept in a hash map, sorting keeps the order stable between calls.
+        streams.sort_by(|a, b| a.quality.cmp(&b.quality));
+        Self {
+            title: song.title().clone(),
+            artist: song.artist().clone(),
+            source_url: song.source_url().clone(),
+            artwork_url: song.artwork_url().clone(),
+            start_time: *song.start_time(),
+            expires_at: *song.expires_at(),
+            streams,
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SearchResultJs {
+    provider: &'static str,
+    title: String,
+    artist: Option<String>,
+    url: String,
+    artwork_url: Option<String>,
+}
+
+fn to_js(value: &impl Serialize) -> Result<JsValue, JsValue> {
+    JsValue::from_serde(value).map_err(|e| e.to_string().into())
+}
+
+fn to_js_error(report: Report) -> JsValue {
+    js_sys::Error::new(&report.to_string()).into()
+}
+
+async fn find_provider(url: &str) -> Result<Rc<dyn Provider>> {
+    providers::all()
+        .await?
+        .into_iter()
+        .find(|provider| provider.is_match(url))
+        .ok_or_else(|| eyre!("no provider supports {}", url))
+}
+
+/// Resolves a link to a song into its details and streams.
+#[wasm_bindgen]
+pub async fn resolve(url: String) -> Result<JsValue, JsValue> {
+    let song = async { find_provider(&url).await?.song_from_url(&url).await }
+        .await
+        .map_err(to_js_error)?;
+    to_js(&SongJs::from(song))
+}
+
+/// Lists the names of the providers this build supports, without setting any of them up.
+///
+/// Still returns a promise, as it did when it had to.
+#[wasm_bindgen(js_name = listProviders)]
+pub async fn list_providers() -> Result<JsValue, JsValue> {
+    to_js(&providers::names())
+}
+
+/// Searches every provider, skipping the ones whose search fails.
+#[wasm_bindgen]
+pub async fn search(query: String) -> Result<JsValue, JsValue> {
+    let providers = providers::all().await.map_err(to_js_error)?;
+    let mut results = Vec::new();
+    for provider in providers {
+        match provider.search(&query).await {
+            Ok(found) => results.extend(found.into_iter().map(|result| SearchResultJs {
+                provider: provider.name(),
+                title: result.title,
+                artist: result.artist,
+                url: result.url,
+                artwork_url: result.artwork_url,
+            })),
+            Err(e) => log::error!("{} search failed: {}", provider.name(), e),
+        }
+    }
+    to_js(&results)
+}
@@ -0,0 +1,143 @@
+//! A compact player for `<iframe>`s, selected with `?embed=1&url=...`.
+//!
+//! Playback events are posted to the parent window and commands are taken from it, both as plain objects
+//! with a `type` field. Events also carry `source: "frick-dmca"` so parents can tell them apart.
+//! Commands are only taken from the parent window, and with `&origin=<origin>` only from that origin.
+
+use crate::model::{Model, Msg};
+use log::{error, warn};
+use seed::{prelude::*, *};
+use serde::{Deserialize, Serialize};
+use web_sys::MessageEvent;
+
+/// Sent to the parent window.
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Event {
+    /// The player is listening for commands.
+    Ready,
+    #[serde(rename_all = "camelCase")]
+    Loaded {
+        title: String,
+        artist: Option<String>,
+        artwork_url: Option<String>,
+        duration: f64,
+    },
+    Play,
+    Pause,
+    #[serde(rename_all = "camelCase")]
+    TimeUpdate {
+        current_time: f64,
+        duration: f64,
+    },
+    Ended,
+    Error {
+        message: String,
+    },
+}
+
+/// Received from the parent window.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Command {
+    Play,
+    Pause,
+    Load { url: String },
+    Seek { time: f64 },
+}
+
+#[derive(Serialize)]
+struct Outgoing<'a> {
+    source: &'static str,
+    #[serde(flatten)]
+    event: &'a Event,
+}
+
+/// Whether the page was opened as an embed.
+pub fn is_requested(url: &Url) -> bool {
+    url.search()
+        .get("embed")
+        .and_then(|values| values.first())
+        .map_or(false, |value| value != "0" && value != "false")
+}
+
+pub fn post(event: &Event) {
+    let window = window();
+    let parent = match window.parent() {
+        Ok(Some(parent)) => parent,
+        _ => return,
+    };
+    // A page that isn't in a frame is its own parent, and would receive its own events as commands.
+    if JsValue::from(parent.clone()) == JsValue::from(window) {
+        return;
+    }
+    let message = match JsValue::from_serde(&Outgoing {
+        source: "frick-dmca",
+        event,
+    }) {
+        Ok(message) => message,
+        Err(e) => return error!("failed to serialize embed event: {}", e),
+    };
+    // Events don't carry anything private, so any embedding page may see them.
+    if parent.post_message(&message, "*").is_err() {
+        warn!("failed to post embed event to the parent window");
+    }
+}
+
+/// The only origin commands are taken from, when the embedding page asked for one.
+fn allowed_origin() -> Option<String> {
+    Url::current().search().get("origin")?.first().cloned()
+}
+
+/// Turns a `message` event into a command, ignoring messages that aren't commands
+/// or don't come from the parent window.
+pub fn command(event: web_sys::Event) -> Option<Command> {
+    let event = event.unchecked_into::<MessageEvent>();
+    // Any other frame or window holding a reference to this one can post to it too.
+    let parent = window().parent().ok()??;
+    if JsValue::from(event.source()?) != JsValue::from(parent) {
+        return None;
+    }
+    if let Some(origin) = allowed_origin() {
+        if event.origin() != origin {
+            return None;
+        }
+    }
+    event.data().into_serde().ok()
+}
+
+pub fn view(model: &Model) -> Node<Msg> {
+    let song = model.currently_playing.as_ref();
+    div![
+        C!["embed"],
+        song.and_then(|song| song.artwork_url().as_ref())
+            .map(|artwork_url| img![attrs! { At::Src => artwork_url; At::Alt => "" }]),
+        div![
+            C!["embed-controls"],
+            span![
+                C!["embed-title"],
+                song.map_or("Loading…", |song| song.title().as_str())
+            ],
+            div![
+                button![
+                    if model.playback.playing { "⏸" } else { "▶" },
+                    attrs! {
+                        At::Disabled => (!model.should_render_audio).as_at_value();
+                    },
+                    ev(Ev::Click, |_| Msg::TogglePlayback),
+                ],
+                input![
+                    attrs! {
+                        At::Type => "range";
+                        At::Min => 0;
+                        At::Max => model.playback.duration;
+                        At::Step => "any";
+                        At::Value => model.playback.current_time;
+                    },
+                    input_ev(Ev::Input, |value| value.parse().ok().map(Msg::Seek)),
+                ],
+            ],
+        ],
+        IF!(model.should_render_audio => crate::model::view_audio(model)),
+    ]
+}
@@ -0,0 +1,39 @@
+//! Moving text in and out of the page as files the user picks or downloads.
+
+use seed::{prelude::*, *};
+use simple_eyre::eyre::{eyre, Result};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{File, HtmlElement, HtmlInputElement};
+
+/// Offers `contents` as a download, through a link to a data url.
+pub fn save(name: &str, mime_type: &str, contents: &str) -> Result<()> {
+    let href = format!(
+        "data:{};charset=utf-8,{}",
+        mime_type,
+        String::from(js_sys::encode_uri_component(contents))
+    );
+    let link = document()
+        .create_element("a")
+        .map_err(|e| eyre!("failed to create a link: {:?}", e))?;
+    let _ = link.set_attribute("href", &href);
+    let _ = link.set_attribute("download", name);
+    link.unchecked_into::<HtmlElement>().click();
+    Ok(())
+}
+
+/// The file picked in the `<input type="file">` an event came from.
+pub fn picked(event: web_sys::Event) -> Option<File> {
+    event
+        .target()?
+        .unchecked_into::<HtmlInputElement>()
+        .files()?
+        .get(0)
+}
+
+pub async fn read(file: &File) -> Result<String> {
+    JsFuture::from(file.text())
+        .await
+        .map_err(|e| eyre!("failed to read {}: {:?}", file.name(), e))?
+        .as_string()
+        .ok_or_else(|| eyre!("{} isn't text", file.name()))
+}
@@ -0,0 +1,206 @@
+//! Every song that was played, kept in IndexedDB and keyed by its canonical url.
+
+use crate::file;
+use crate::idb::Database;
+use crate::providers::Song;
+use seed::{prelude::*, *};
+use serde::{Deserialize, Serialize};
+use simple_eyre::eyre::Result;
+use std::cmp::Ordering;
+
+const STORE: &str = "history";
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Entry {
+    pub url: String,
+    pub provider: String,
+    pub title: String,
+    pub artist: Option<String>,
+    pub artwork_url: Option<String>,
+    /// Milliseconds since the epoch, like `Date.now()`.
+    pub first_played: f64,
+    pub last_played: f64,
+    pub play_count: u32,
+}
+
+impl Entry {
+    /// An entry for a song played just now, as if it was the first time.
+    pub fn played(song: &Song, provider: &str) -> Self {
+        let now = js_sys::Date::now();
+        Self {
+            url: song.source_url().clone(),
+            provider: provider.to_owned(),
+            title: song.title().clone(),
+            artist: song.artist().clone(),
+            artwork_url: song.artwork_url().clone(),
+            first_played: now,
+            last_played: now,
+            play_count: 1,
+        }
+    }
+
+    fn matches(&self, filter: &str) -> bool {
+        [
+            Some(&self.title),
+            self.artist.as_ref(),
+            Some(&self.provider),
+        ]
+        .iter()
+        .flatten()
+        .any(|field| field.to_lowercase().contains(filter))
+    }
+}
+
+async fn record_entry(played: Entry) -> Result<Entry> {
+    let database = Database::open().await?;
+    let entry = match database.get::<Entry>(STORE, &played.url).await? {
+        Some(previous) => Entry {
+            first_played: previous.first_played,
+            play_count: previous.play_count + 1,
+            ..played
+        },
+        None => played,
+    };
+    database.put(STORE, &entry).await?;
+    Ok(entry)
+}
+
+async fn entries() -> Result<Vec<Entry>> {
+    Database::open().await?.get_all(STORE).await
+}
+
+async fn clear() -> Result<()> {
+    Database::open().await?.clear(STORE).await
+}
+
+pub async fn load() -> Msg {
+    Msg::Loaded(entries().await.map_err(|e| e.to_string()))
+}
+
+pub async fn record(played: Entry) -> Msg {
+    Msg::Recorded(record_entry(played).await.map_err(|e| e.to_string()))
+}
+
+// ------ ------
+//     Model
+// ------ ------
+
+#[derive(Clone, Default)]
+pub struct Model {
+    /// Most recently played first.
+    pub entries: Vec<Entry>,
+    pub filter: String,
+    pub error: Option<String>,
+}
+
+// ------ ------
+//    Update
+// ------ ------
+
+pub enum Msg {
+    Loaded(Result<Vec<Entry>, String>),
+    Recorded(Result<Entry, String>),
+    FilterChanged(String),
+    /// Handled by the parent, which loads the song.
+    PlayAgain(String),
+    Clear,
+    Cleared(Result<(), String>),
+    Export,
+}
+
+pub fn update(msg: Msg, model: &mut Model, orders: &mut impl Orders<Msg>) {
+    match msg {
+        Msg::Loaded(Ok(mut entries)) => {
+            entries.sort_by(|a, b| {
+                b.last_played
+                    .partial_cmp(&a.last_played)
+                    .unwrap_or(Ordering::Equal)
+            });
+            model.entries = entries;
+        }
+        Msg::Recorded(Ok(entry)) => {
+            model.entries.retain(|existing| existing.url != entry.url);
+            model.entries.insert(0, entry);
+        }
+        Msg::Loaded(Err(e)) | Msg::Recorded(Err(e)) | Msg::Cleared(Err(e)) => {
+            model.error = Some(e);
+        }
+        Msg::FilterChanged(filter) => {
+            model.filter = filter;
+        }
+        Msg::PlayAgain(_) => {}
+        Msg::Clear => {
+            orders.perform_cmd(async { Msg::Cleared(clear().await.map_err(|e| e.to_string())) });
+        }
+        Msg::Cleared(Ok(())) => {
+            model.entries.clear();
+            model.error = None;
+        }
+        Msg::Export => {
+            orders.skip();
+            let saved = serde_json::to_string_pretty(&model.entries)
+                .map_err(Into::into)
+                .and_then(|json| file::save("frick-dmca-history.json", "application/json", &json));
+            if let Err(e) = saved {
+                model.error = Some(e.to_string());
+                orders.render();
+            }
+        }
+    }
+}
+
+// ------ ------
+//     View
+// ------ ------
+
+pub fn view(model: &Model) -> Node<Msg> {
+    let filter = model.filter.to_lowercase();
+    section![
+        C!["history"],
+        h3!["History"],
+        div![
+            C!["row"],
+            input![
+                attrs! {
+                    At::Type => "search";
+                    At::Placeholder => "Filter by title, artist or provider";
+                    At::Value => model.filter;
+                },
+                input_ev(Ev::Input, Msg::FilterChanged),
+            ],
+            button!["Export", ev(Ev::Click, |_| Msg::Export)],
+            button!["Clear", ev(Ev::Click, |_| Msg::Clear)],
+        ],
+        model.error.as_ref().map(|error| p![C!["error"], error]),
+        IF!(model.entries.is_empty() => p!["Nothing played yet."]),
+        ul![model
+            .entries
+            .iter()
+            .filter(|entry| entry.matches(&filter))
+            .map(view_entry)],
+    ]
+}
+
+fn view_entry(entry: &Entry) -> Node<Msg> {
+    let url = entry.url.clone();
+    let last_played = js_sys::Date::new(&JsValue::from_f64(entry.last_played))
+        .to_locale_string("default", &JsValue::UNDEFINED);
+    li![
+        div![
+            span![C!["history-title"], &entry.title],
+            entry
+                .artist
+                .as_ref()
+                .map(|artist| span![format!(" by {}", artist)]),
+        ],
+        small![format!(
+            "{}, played {} time{}, last on {}",
+            entry.provider,
+            entry.play_count,
+            if entry.play_count == 1 { "" } else { "s" },
+            String::from(last_played)
+        )],
+        button!["Play again", ev(Ev::Click, move |_| Msg::PlayAgain(url))],
+    ]
+}
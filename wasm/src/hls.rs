@@ -14,4 +14,10 @@ extern "C" {
     pub fn attach_media(this: &Hls, el: HtmlMediaElement);
     #[wasm_bindgen(js_name = isSupported, static_method_of = Hls)]
     pub fn is_supported() -> bool;
+    /// Listens to one of `Hls.Events`, with a callback taking the event's name and data.
+    #[wasm_bindgen(method)]
+    pub fn on(this: &Hls, event: &str, callback: &JsValue);
 }
+
+/// `Hls.Events.ERROR`, whose data has a `fatal` flag for errors hls.js doesn't recover from.
+pub const ERROR_EVENT: &str = "hlsError";
@@ -0,0 +1,99 @@
+use bridge::{Envelope, Event, HostMessage, Request, Response, RECEIVE_FUNCTION};
+use futures::channel::oneshot;
+use log::*;
+use seed::{prelude::*, window};
+use simple_eyre::eyre::{eyre, Result};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+
+thread_local! {
+    static PENDING: RefCell<HashMap<u32, oneshot::Sender<Response>>> = RefCell::new(HashMap::new());
+    static NEXT_ID: Cell<u32> = Cell::new(0);
+    static EVENT_HANDLER: RefCell<Option<Box<dyn Fn(Event)>>> = RefCell::new(None);
+}
+
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_namespace = external, js_name = invoke, catch)]
+    fn invoke(arg: &str) -> Result<(), JsValue>;
+}
+
+/// Whether the page runs inside the desktop app, which provides `window.external.invoke`.
+pub fn is_available() -> bool {
+    js_sys::Reflect::get(&window(), &JsValue::from_str("external"))
+        .and_then(|external| js_sys::Reflect::get(&external, &JsValue::from_str("invoke")))
+        .map_or(false, |invoke| invoke.is_function())
+}
+
+/// Registers the global function the host calls to deliver [`HostMessage`]s.
+pub fn install() -> Result<()> {
+    let receive = Closure::wrap(Box::new(receive) as Box<dyn Fn(String)>);
+    js_sys::Reflect::set(
+        &window(),
+        &JsValue::from_str(RECEIVE_FUNCTION),
+        receive.as_ref(),
+    )
+    .map_err(|_| eyre!("failed to register {}", RECEIVE_FUNCTION))?;
+    // The host may call it for the whole lifetime of the page.
+    receive.forget();
+    Ok(())
+}
+
+/// Sets the function [`Event`]s from the host are passed to.
+pub fn on_event(handler: impl Fn(Event) + 'static) {
+    EVENT_HANDLER.with(|event_handler| *event_handler.borrow_mut() = Some(Box::new(handler)));
+}
+
+fn receive(json: String) {
+    match serde_json::from_str::<HostMessage>(&json) {
+        Ok(HostMessage::Reply { id, response }) => {
+            match PENDING.with(|pending| pending.borrow_mut().remove(&id)) {
+                Some(sender) => {
+                    let _ = sender.send(response);
+                }
+                None => warn!("got a reply to unknown request {}", id),
+            }
+        }
+        Ok(HostMessage::Event { event }) => {
+            EVENT_HANDLER.with(|event_handler| match event_handler.borrow().as_ref() {
+                Some(handler) => handler(event),
+                None => warn!("no handler for host event {:?}", event),
+            })
+        }
+        Err(e) => error!("failed to parse host message {}: {}", json, e),
+    }
+}
+
+fn post(envelope: &Envelope) -> Result<()> {
+    let json = serde_json::to_string(envelope)?;
+    trace!("sending to host: {}", json);
+    invoke(&json).map_err(|_| eyre!("failed to invoke the host"))
+}
+
+/// Sends a request to the host and waits for its reply.
+pub async fn call(request: Request) -> Result<Response> {
+    let id = NEXT_ID.with(|next_id| {
+        let id = next_id.get();
+        next_id.set(id.wrapping_add(1));
+        id
+    });
+    let (sender, receiver) = oneshot::channel();
+    PENDING.with(|pending| pending.borrow_mut().insert(id, sender));
+
+    if let Err(e) = post(&Envelope {
+        id: Some(id),
+        request,
+    }) {
+        PENDING.with(|pending| pending.borrow_mut().remove(&id));
+        return Err(e);
+    }
+
+    receiver
+        .await
+        .map_err(|_| eyre!("the host dropped request {}", id))
+}
+
+/// Sends a request without waiting for a reply, for when its effects show up as [`Event`]s anyway.
+pub fn send(request: Request) -> Result<()> {
+    post(&Envelope { id: None, request })
+}
@@ -0,0 +1,132 @@
+//! A small promise-style wrapper around IndexedDB, for data that doesn't fit in local storage.
+
+use seed::prelude::{js_sys::Promise, *};
+use serde::{de::DeserializeOwned, Serialize};
+use simple_eyre::eyre::{eyre, Report, Result};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{IdbDatabase, IdbObjectStoreParameters, IdbRequest, IdbTransactionMode};
+
+const NAME: &str = "frick-dmca";
+/// Bumped whenever a store is added, which creates the missing ones.
+const VERSION: u32 = 2;
+/// Every object store and the property its values are keyed by.
+const STORES: &[(&str, &str)] = &[("history", "url"), ("playlists", "id")];
+
+fn js_error(error: JsValue) -> Report {
+    eyre!(
+        "{}",
+        error
+            .dyn_ref::<js_sys::Error>()
+            .map_or_else(|| format!("{:?}", error), |e| String::from(e.message()))
+    )
+}
+
+/// Waits for a request to finish and returns its result.
+async fn finish(request: &IdbRequest) -> Result<JsValue> {
+    let promise = Promise::new(&mut |resolve, reject| {
+        let succeeded = request.clone();
+        let failed = request.clone();
+        // Only one of these is ever called, the other one leaks, which is a few bytes per request.
+        let on_success = Closure::once_into_js(move |_: web_sys::Event| {
+            let _ = resolve.call1(
+                &JsValue::NULL,
+                &succeeded.result().unwrap_or(JsValue::UNDEFINED),
+            );
+        });
+        let on_error = Closure::once_into_js(move |_: web_sys::Event| {
+            let error = failed
+                .error()
+                .ok()
+                .flatten()
+                .map_or(JsValue::NULL, JsValue::from);
+            let _ = reject.call1(&JsValue::NULL, &error);
+        });
+        request.set_onsuccess(Some(on_success.unchecked_ref()));
+        request.set_onerror(Some(on_error.unchecked_ref()));
+    });
+    JsFuture::from(promise).await.map_err(js_error)
+}
+
+pub struct Database(IdbDatabase);
+
+impl Database {
+    pub async fn open() -> Result<Self> {
+        let factory = seed::window()
+            .indexed_db()
+            .map_err(js_error)?
+            .ok_or_else(|| eyre!("IndexedDB isn't available"))?;
+        let request = factory.open_with_u32(NAME, VERSION).map_err(js_error)?;
+        let upgrading = request.clone();
+        let on_upgrade_needed = Closure::once_into_js(move |_: web_sys::Event| {
+            let database = match upgrading.result() {
+                Ok(result) => IdbDatabase::from(result),
+                Err(_) => return,
+            };
+            for (name, key_path) in STORES {
+                if !database.object_store_names().contains(name) {
+                    let mut parameters = IdbObjectStoreParameters::new();
+                    parameters.key_path(Some(&JsValue::from_str(key_path)));
+                    let _ =
+                        database.create_object_store_with_optional_parameters(name, &parameters);
+                }
+            }
+        });
+        request.set_onupgradeneeded(Some(on_upgrade_needed.unchecked_ref()));
+        Ok(Self(finish(&request).await?.into()))
+    }
+
+    fn store(&self, name: &str, mode: IdbTransactionMode) -> Result<web_sys::IdbObjectStore> {
+        self.0
+            .transaction_with_str_and_mode(name, mode)
+            .and_then(|transaction| transaction.object_store(name))
+            .map_err(js_error)
+    }
+
+    pub async fn get<T: DeserializeOwned>(&self, store: &str, key: &str) -> Result<Option<T>> {
+        let request = self
+            .store(store, IdbTransactionMode::Readonly)?
+            .get(&JsValue::from_str(key))
+            .map_err(js_error)?;
+        let value = finish(&request).await?;
+        if value.is_undefined() {
+            return Ok(None);
+        }
+        Ok(Some(value.into_serde()?))
+    }
+
+    pub async fn get_all<T: DeserializeOwned>(&self, store: &str) -> Result<Vec<T>> {
+        let request = self
+            .store(store, IdbTransactionMode::Readonly)?
+            .get_all()
+            .map_err(js_error)?;
+        Ok(finish(&request).await?.into_serde()?)
+    }
+
+    /// Inserts `value`, replacing the one with the same key if there is one.
+    pub async fn put<T: Serialize>(&self, store: &str, value: &T) -> Result<()> {
+        let request = self
+            .store(store, IdbTransactionMode::Readwrite)?
+            .put(&JsValue::from_serde(value)?)
+            .map_err(js_error)?;
+        finish(&request).await?;
+        Ok(())
+    }
+
+    pub async fn delete(&self, store: &str, key: &str) -> Result<()> {
+        let request = self
+            .store(store, IdbTransactionMode::Readwrite)?
+            .delete(&JsValue::from_str(key))
+            .map_err(js_error)?;
+        finish(&request).await?;
+        Ok(())
+    }
+
+    pub async fn clear(&self, store: &str) -> Result<()> {
+        let request = self
+            .store(store, IdbTransactionMode::Readwrite)?
+            .clear()
+            .map_err(js_error)?;
+        finish(&request).await?;
+        Ok(())
+    }
+}
@@ -1,10 +1,21 @@
 #![feature(bool_to_option)]
 
+mod api;
+mod embed;
+mod file;
+mod history;
 mod hls;
+mod host;
+mod idb;
+mod media_session;
 mod model;
+mod playlists;
 mod providers;
+mod route;
+mod settings;
+mod window;
 
-use crate::model::{update, view, Model};
+use crate::model::{update, view, Model, Msg};
 use seed::prelude::*;
 
 #[wasm_bindgen(start)]
@@ -16,7 +27,19 @@ pub async fn run() -> Result<(), JsValue> {
         console_log::init().map_err(|e| e.to_string())?;
     }
 
+    host::install().map_err(|e| e.to_string())?;
+    // Pages that only use the exports in `api` don't have anywhere to mount the app.
+    if seed::document().get_element_by_id("app").is_none() {
+        return Ok(());
+    }
+
     let model = Model::new().await.map_err(|e| e.to_string())?;
-    App::start("app", |_url, _orders| model, update, view);
+    let app = App::start(
+        "app",
+        |url, orders| model::init(model, url, orders),
+        update,
+        view,
+    );
+    host::on_event(move |event| app.update(Msg::HostEvent(event)));
     Ok(())
 }
@@ -0,0 +1,169 @@
+//! `navigator.mediaSession`, which feeds media keys, notification controls and lock screens.
+//!
+//! web-sys only has bindings for it behind `--cfg=web_sys_unstable_apis`, so the few bits used here are
+//! bound by hand. Browsers without it are silently left alone.
+
+use crate::providers::Song;
+use log::debug;
+use seed::prelude::*;
+use serde::Serialize;
+use std::rc::Rc;
+
+#[wasm_bindgen]
+extern "C" {
+    type MediaSession;
+
+    #[wasm_bindgen(method, setter)]
+    fn set_metadata(this: &MediaSession, metadata: Option<&MediaMetadata>);
+    #[wasm_bindgen(method, setter = playbackState)]
+    fn set_playback_state(this: &MediaSession, state: &str);
+    #[wasm_bindgen(method, catch, js_name = setActionHandler)]
+    fn set_action_handler(
+        this: &MediaSession,
+        action: &str,
+        handler: &JsValue,
+    ) -> Result<(), JsValue>;
+    #[wasm_bindgen(method, catch, js_name = setPositionState)]
+    fn set_position_state(this: &MediaSession, state: &JsValue) -> Result<(), JsValue>;
+
+    type MediaMetadata;
+
+    #[wasm_bindgen(constructor, catch)]
+    fn new(init: &JsValue) -> Result<MediaMetadata, JsValue>;
+}
+
+#[derive(Serialize)]
+struct MetadataInit<'a> {
+    title: &'a str,
+    artist: &'a str,
+    artwork: Vec<Artwork<'a>>,
+}
+
+#[derive(Serialize)]
+struct Artwork<'a> {
+    src: &'a str,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PositionState {
+    duration: f64,
+    playback_rate: f64,
+    position: f64,
+}
+
+/// What the OS asks the player to do.
+pub enum Action {
+    Play,
+    Pause,
+    /// To a position in seconds.
+    SeekTo(f64),
+    /// By an offset in seconds, negative for backwards.
+    SeekBy(f64),
+    Next,
+    Previous,
+}
+
+/// Seconds skipped by the seek backward and forward buttons when the OS doesn't say.
+const DEFAULT_SEEK_OFFSET: f64 = 10.0;
+
+fn session() -> Option<MediaSession> {
+    let session = js_sys::Reflect::get(
+        &seed::window().navigator(),
+        &JsValue::from_str("mediaSession"),
+    )
+    .ok()?;
+    (!session.is_undefined()).then(|| session.unchecked_into())
+}
+
+fn number(details: &JsValue, name: &str) -> Option<f64> {
+    js_sys::Reflect::get(details, &JsValue::from_str(name))
+        .ok()?
+        .as_f64()
+}
+
+/// Routes the OS's media controls to `handler`.
+pub fn install(handler: impl Fn(Action) + 'static) {
+    let session = match session() {
+        Some(session) => session,
+        None => return debug!("media session isn't supported"),
+    };
+    let handler = Rc::new(handler);
+    let actions: [(&str, fn(&JsValue) -> Option<Action>); 7] = [
+        ("play", |_| Some(Action::Play)),
+        ("pause", |_| Some(Action::Pause)),
+        ("seekto", |details| {
+            number(details, "seekTime").map(Action::SeekTo)
+        }),
+        ("seekbackward", |details| {
+            let offset = number(details, "seekOffset").unwrap_or(DEFAULT_SEEK_OFFSET);
+            Some(Action::SeekBy(-offset))
+        }),
+        ("seekforward", |details| {
+            let offset = number(details, "seekOffset").unwrap_or(DEFAULT_SEEK_OFFSET);
+            Some(Action::SeekBy(offset))
+        }),
+        ("nexttrack", |_| Some(Action::Next)),
+        ("previoustrack", |_| Some(Action::Previous)),
+    ];
+    for (name, action) in actions.iter().copied() {
+        let handler = handler.clone();
+        let callback = Closure::wrap(Box::new(move |details: JsValue| {
+            if let Some(action) = action(&details) {
+                handler(action);
+            }
+        }) as Box<dyn Fn(JsValue)>);
+        // Browsers throw for actions they don't know about.
+        if session.set_action_handler(name, callback.as_ref()).is_err() {
+            debug!("media session action {} isn't supported", name);
+        }
+        // The handlers stay installed for as long as the page is open.
+        callback.forget();
+    }
+}
+
+/// Shows `song` in the OS's media controls, or clears them.
+pub fn set_song(song: Option<&Song>) {
+    let session = match session() {
+        Some(session) => session,
+        None => return,
+    };
+    let metadata = song.and_then(|song| {
+        let init = MetadataInit {
+            title: song.title(),
+            artist: song.artist().as_deref().unwrap_or(""),
+            artwork: song
+                .artwork_url()
+                .iter()
+                .map(|src| Artwork { src })
+                .collect(),
+        };
+        JsValue::from_serde(&init)
+            .ok()
+            .and_then(|init| MediaMetadata::new(&init).ok())
+    });
+    session.set_metadata(metadata.as_ref());
+}
+
+pub fn set_playing(playing: bool) {
+    if let Some(session) = session() {
+        session.set_playback_state(if playing { "playing" } else { "paused" });
+    }
+}
+
+/// Lets lock screens draw a progress bar, ignored for streams without a known length.
+pub fn set_position(position: f64, duration: f64) {
+    if !duration.is_finite() || duration <= 0.0 {
+        return;
+    }
+    if let Some(session) = session() {
+        let state = PositionState {
+            duration,
+            playback_rate: 1.0,
+            position: position.min(duration).max(0.0),
+        };
+        if let Ok(state) = JsValue::from_serde(&state) {
+            let _ = session.set_position_state(&state);
+        }
+    }
+}
@@ -1,20 +1,37 @@
 // use crate::hls::set_hls_source;
-use crate::hls::Hls;
-#[cfg(feature = "soundcloud")]
-use crate::providers::SoundCloud;
-#[cfg(feature = "youtube")]
-use crate::providers::YouTube;
-use crate::providers::{Provider, Song};
-use log::debug;
+use crate::embed;
+use crate::history;
+use crate::hls::{self, Hls};
+use crate::host;
+use crate::media_session;
+use crate::playlists;
+use crate::providers::{self, Provider, Quality, Song};
+use crate::route::Route;
+use crate::settings;
+use crate::window;
+use bridge::{
+    Control, Download, DownloadState, Event, Metadata, PlaybackStatus, Request, Response,
+    WindowGeometry,
+};
+use log::{debug, error};
 use seed::{
     prelude::{web_sys::HtmlSelectElement, *},
     *,
 };
 use simple_eyre::eyre::Result;
+use std::collections::BTreeMap;
 use std::rc::Rc;
-use web_sys::HtmlAudioElement;
+use web_sys::{HtmlAudioElement, HtmlMediaElement};
 
 const ENTER_KEY: u32 = 13;
+/// Streams that fail again this many seconds after being resolved again aren't retried.
+const REFRESH_COOLDOWN: f64 = 30.0;
+/// How long before they expire streams are resolved again when playback starts.
+const EXPIRY_MARGIN: f64 = 60.0;
+/// Milliseconds the window has to stay the same size before its geometry is saved.
+const WINDOW_SAVE_DELAY: u32 = 500;
+/// Milliseconds between checks for the window having moved, which pages aren't told about.
+const WINDOW_POLL_INTERVAL: u32 = 2000;
 
 // ------ ------
 //     Model
@@ -32,17 +49,71 @@ pub struct Model {
     pub hls: Hls,
     pub hls_supported: bool,
     pub should_render_sources: bool,
+    pub pending_seek: Option<f64>,
+    pub settings: settings::Model,
+    pub show_settings: bool,
+    pub history: history::Model,
+    pub show_history: bool,
+    pub playlists: playlists::Model,
+    pub show_playlists: bool,
+    pub host_available: bool,
+    pub downloads: BTreeMap<u32, Download>,
+    pub parallelism: usize,
+    pub download_error: Option<String>,
+    pub embed: bool,
+    pub playback: Playback,
+    /// Taken from the page url, applied once the song it links to has loaded.
+    pub requested_quality: Option<String>,
+    pub requested_start_time: Option<f64>,
+    /// Whether the song's own start time has been seeked to, which only happens the first time it loads.
+    pub start_time_applied: bool,
+    /// Whether the song being loaded gets a history entry, which it doesn't when it came from the url.
+    pub push_route: bool,
+    /// Set while the current song is resolved again because its streams stopped working.
+    pub refresh: Option<Refresh>,
+    /// Seconds since the epoch.
+    pub refreshed_at: Option<f64>,
+    /// Whether to play or pause once refreshed streams have loaded, to carry on as before.
+    pub play_after_load: Option<bool>,
+    /// The playlist and index the current song was played from, which next and previous move through.
+    pub playing_from: Option<(String, usize)>,
+    /// The desktop app's window geometry as last saved, missing until the host has sent it.
+    pub window: Option<WindowGeometry>,
+    /// Counts resizes, so only the last one of a burst saves the geometry.
+    pub window_changes: u32,
+}
+
+/// Where playback was when the streams stopped working.
+#[derive(Clone, Copy)]
+pub struct Refresh {
+    pub position: f64,
+    pub playing: bool,
+}
+
+/// What the embedded player's controls show, kept in sync with the audio element.
+#[derive(Clone, Default)]
+pub struct Playback {
+    pub playing: bool,
+    pub current_time: f64,
+    pub duration: f64,
+}
+
+impl Playback {
+    fn read(audio: &HtmlAudioElement) -> Self {
+        let or_zero = |value: f64| if value.is_finite() { value } else { 0.0 };
+        Self {
+            playing: !audio.paused(),
+            current_time: or_zero(audio.current_time()),
+            duration: or_zero(audio.duration()),
+        }
+    }
 }
 
 impl Model {
     pub async fn new() -> Result<Self> {
-        let mut providers = Vec::<Rc<dyn Provider>>::new();
-        #[cfg(feature = "soundcloud")]
-        providers.push(Rc::new(SoundCloud::new().await?));
-        #[cfg(feature = "youtube")]
-        providers.push(Rc::new(YouTube::new()));
+        let settings = settings::load().await;
         Ok(Self {
-            providers,
+            providers: providers::from_config(&settings.providers).await?,
             song_url: String::new(),
             currently_playing: None,
             selected_quality: None,
@@ -52,58 +123,280 @@ impl Model {
             hls: Hls::new(),
             hls_supported: Hls::is_supported(),
             should_render_sources: false,
+            pending_seek: None,
+            settings: settings::Model::new(settings),
+            show_settings: false,
+            history: history::Model::default(),
+            show_history: false,
+            playlists: playlists::Model::default(),
+            show_playlists: false,
+            host_available: host::is_available(),
+            downloads: BTreeMap::new(),
+            parallelism: 1,
+            download_error: None,
+            embed: false,
+            playback: Playback::default(),
+            requested_quality: None,
+            requested_start_time: None,
+            start_time_applied: false,
+            push_route: false,
+            refresh: None,
+            refreshed_at: None,
+            play_after_load: None,
+            playing_from: None,
+            window: None,
+            window_changes: 0,
         })
     }
 }
 
+/// Loads the song the page url links to, see [`Route`].
+pub fn init(mut model: Model, url: Url, orders: &mut impl Orders<Msg>) -> Model {
+    model.embed = embed::is_requested(&url);
+    if model.embed {
+        orders.stream(streams::window_event(Ev::Message, |event| {
+            embed::command(event).map(Msg::EmbedCommand)
+        }));
+        embed::post(&embed::Event::Ready);
+    } else {
+        orders.subscribe(|subs::UrlChanged(url)| Msg::RouteChanged(url));
+        orders.proxy(Msg::History).perform_cmd(history::load());
+        orders.proxy(Msg::Playlists).perform_cmd(playlists::load());
+    }
+    let sender = orders.msg_sender();
+    let on_hls_error = Closure::wrap(Box::new(move |_event: JsValue, data: JsValue| {
+        let fatal = js_sys::Reflect::get(&data, &JsValue::from_str("fatal"))
+            .map_or(false, |fatal| fatal.as_bool() == Some(true));
+        if fatal {
+            sender(Some(Msg::StreamFailed));
+        }
+    }) as Box<dyn Fn(JsValue, JsValue)>);
+    model.hls.on(hls::ERROR_EVENT, on_hls_error.as_ref());
+    // The hls.js instance lives as long as the page.
+    on_hls_error.forget();
+    let sender = orders.msg_sender();
+    media_session::install(move |action| sender(Some(Msg::MediaSession(action))));
+    orders.send_msg(Msg::RouteChanged(url));
+    if model.host_available {
+        orders.perform_cmd(list_downloads());
+    }
+    if model.host_available && !model.embed {
+        orders
+            .stream(streams::window_event(Ev::Resize, |_| Msg::WindowChanged))
+            .stream(streams::interval(WINDOW_POLL_INTERVAL, || Msg::SaveWindow))
+            .stream(streams::window_event(Ev::BeforeUnload, |_| Msg::SaveWindow))
+            .perform_cmd(restore_window());
+    }
+    model
+}
+
 // ------ ------
 //    Update
 // ------ ------
 
 pub enum Msg {
     SubmitUrl,
+    RouteChanged(Url),
     UrlChanged(String),
     NewSong(Song),
     QualityChanged(String),
     AudioLoaded(String),
+    MetadataLoaded,
+    ToggleSettings,
+    Settings(settings::Msg),
+    ProvidersLoaded(Result<Vec<Rc<dyn Provider>>, String>),
+    ToggleHistory,
+    History(history::Msg),
+    TogglePlaylists,
+    Playlists(playlists::Msg),
+    /// Adds the current song to the playlist with this id.
+    AddToPlaylist(String),
+    Download,
+    DownloadError(String),
+    DownloadsListed(Vec<Download>, usize),
+    ParallelismChanged(String),
+    /// Sends a request that isn't answered, like the ones controlling a download, whose new state
+    /// comes back as a [`Event::DownloadUpdated`].
+    SendToHost(Request),
+    HostEvent(Event),
+    SongFailed(String),
+    /// The audio element or hls.js couldn't load the stream, usually because its url expired.
+    StreamFailed,
+    SongRefreshed(Song),
+    TogglePlayback,
+    Seek(f64),
+    Media(MediaEvent),
+    EmbedCommand(embed::Command),
+    MediaSession(media_session::Action),
+    WindowRestored(WindowGeometry),
+    WindowChanged,
+    /// Carries the number of resizes when it was sent, see [`Model::window_changes`].
+    WindowSettled(u32),
+    SaveWindow,
+    /// Moves through the playlist the song was played from, or through the page's history.
+    Next,
+    Previous,
+}
+
+pub enum MediaEvent {
+    Play,
+    Pause,
+    TimeUpdate,
+    Ended,
+}
+
+/// Asks the host where the window was left last time.
+async fn restore_window() -> Option<Msg> {
+    match host::call(Request::GetWindow).await {
+        Ok(Response::Window { geometry }) => Some(Msg::WindowRestored(geometry)),
+        Ok(_) => None,
+        Err(e) => {
+            error!("failed to get the window's geometry: {}", e);
+            None
+        }
+    }
+}
+
+/// Fetches the downloads the host already knows about.
+pub async fn list_downloads() -> Option<Msg> {
+    match host::call(Request::ListDownloads).await {
+        Ok(Response::Downloads {
+            downloads,
+            parallelism,
+        }) => Some(Msg::DownloadsListed(downloads, parallelism)),
+        Ok(response) => {
+            error!("unexpected response to listing downloads: {:?}", response);
+            None
+        }
+        Err(e) => {
+            error!("failed to list downloads: {}", e);
+            None
+        }
+    }
 }
 
 pub fn update(msg: Msg, model: &mut Model, orders: &mut impl Orders<Msg>) {
     match msg {
         Msg::SubmitUrl => {
-            // let model = model.clone();
-            model.should_render_audio = false;
-            let song_url = model.song_url.clone();
-            let providers = model.providers.clone();
-            providers
-                .into_iter()
-                .find(|p| p.is_match(&song_url))
-                .map(|p| {
-                    orders.perform_cmd(async move {
-                        p.song_from_url(&song_url).await.ok().map(Msg::NewSong)
-                    })
-                });
+            model.playing_from = None;
+            model.push_route = !model.embed;
+            model.requested_quality = None;
+            model.requested_start_time = None;
+            load_song(model, orders);
+        }
+        Msg::RouteChanged(url) => {
+            let route = Route::from_url(&url);
+            if let Some(song_url) = route.url {
+                if song_url == model.song_url && model.currently_playing.is_some() {
+                    if let Some(quality) = route
+                        .quality
+                        .filter(|quality| model.selected_quality.as_ref() != Some(quality))
+                    {
+                        orders.send_msg(Msg::QualityChanged(quality));
+                    }
+                } else {
+                    model.song_url = song_url;
+                    model.requested_quality = route.quality;
+                    model.requested_start_time = route.start_time;
+                    model.push_route = false;
+                    load_song(model, orders);
+                }
+            }
         }
         Msg::UrlChanged(edited_url) => {
             orders.skip();
             model.song_url = edited_url;
         }
         Msg::NewSong(song) => {
+            if model.push_route {
+                model.push_route = false;
+                Route {
+                    url: Some(model.song_url.clone()),
+                    ..Route::default()
+                }
+                .push();
+            }
+            // Embeds play whatever the embedding page wants, which isn't the user's history.
+            if !model.embed {
+                if let Some(provider) = model
+                    .providers
+                    .iter()
+                    .find(|p| p.is_match(song.source_url()))
+                {
+                    let played = history::Entry::played(&song, provider.name());
+                    orders
+                        .proxy(Msg::History)
+                        .perform_cmd(history::record(played));
+                }
+            }
+            // A quality from the url wins, otherwise the policy picks one so playback starts right away.
+            let quality = model
+                .requested_quality
+                .take()
+                .and_then(|quality| {
+                    let key = song.quality_key(&quality);
+                    if key.is_none() {
+                        error!(
+                            "{} has no quality {}, letting the policy pick",
+                            song.title(),
+                            quality
+                        );
+                    }
+                    key
+                })
+                .or_else(|| model.settings.current.quality_policy.pick(&song));
+            if let Some(quality) = quality {
+                orders.send_msg(Msg::QualityChanged(quality));
+            }
+            media_session::set_song(Some(&song));
+            if model.host_available && !model.embed && model.settings.current.notifications {
+                orders.send_msg(Msg::SendToHost(Request::Notify {
+                    song: metadata(&song),
+                }));
+            }
             model.currently_playing = Some(song);
             model.selected_quality = None;
+            model.start_time_applied = false;
+            model.playback = Playback::default();
+            report_playback(model);
             if let Some(select) = model.select_ref.get() {
                 select.set_selected_index(0);
             }
         }
         Msg::QualityChanged(quality) => {
+            if !model.embed {
+                Route {
+                    quality: Some(quality.clone()),
+                    ..Route::from_url(&Url::current())
+                }
+                .replace();
+            }
             model.selected_quality = Some(quality.clone());
             model.should_render_audio = true;
             orders.after_next_render(|_| Msg::AudioLoaded(quality));
         }
 
         Msg::AudioLoaded(selected_quality) => {
+            if let Some(select) = model.select_ref.get() {
+                // The quality might not have been picked with the select, but from the url.
+                select.set_value(&selected_quality);
+            }
             if let Some(song) = model.currently_playing.as_ref() {
-                for (is_hls, _mime_type, url) in song.urls[&selected_quality].iter() {
+                // Other qualities of a song that's already playing carry on from where it was.
+                let start_time = if model.start_time_applied {
+                    model
+                        .audio_ref
+                        .get()
+                        .map(|audio| audio.current_time())
+                        .filter(|time| *time > 0.0)
+                } else {
+                    *song.start_time()
+                };
+                model.start_time_applied = true;
+                // Seeking before the source has loaded is unreliable with hls.js,
+                // so the seek happens once the metadata is in.
+                model.pending_seek = model.requested_start_time.take().or(start_time);
+                for (is_hls, _mime_type, url) in song.urls()[&selected_quality].iter() {
                     if let Some(audio) = model.audio_ref.get() {
                         if *is_hls {
                             if model.hls_supported {
@@ -123,6 +416,496 @@ pub fn update(msg: Msg, model: &mut Model, orders: &mut impl Orders<Msg>) {
                 }
             }
         }
+        Msg::MetadataLoaded => {
+            orders.skip();
+            if let (Some(audio), Some(start_time)) =
+                (model.audio_ref.get(), model.pending_seek.take())
+            {
+                debug!("seeking to {}s", start_time);
+                audio.set_current_time(start_time);
+            }
+            if let Some(audio) = model.audio_ref.get() {
+                media_session::set_position(audio.current_time(), audio.duration());
+            }
+            report_playback(model);
+            if let (Some(audio), Some(play)) = (model.audio_ref.get(), model.play_after_load.take())
+            {
+                if play {
+                    let _ = audio.play();
+                } else {
+                    let _ = audio.pause();
+                }
+            }
+            if model.embed {
+                if let (Some(audio), Some(song)) =
+                    (model.audio_ref.get(), model.currently_playing.as_ref())
+                {
+                    model.playback = Playback::read(&audio);
+                    embed::post(&embed::Event::Loaded {
+                        title: song.title().clone(),
+                        artist: song.artist().clone(),
+                        artwork_url: song.artwork_url().clone(),
+                        duration: model.playback.duration,
+                    });
+                    orders.render();
+                }
+            }
+        }
+        Msg::ToggleSettings => {
+            model.show_settings = !model.show_settings;
+        }
+        Msg::Settings(msg) => {
+            let providers_changed = matches!(
+                msg,
+                settings::Msg::InvidiousInstanceChanged(_) | settings::Msg::ToggleProvider(_)
+            );
+            settings::update(msg, &mut model.settings, &mut orders.proxy(Msg::Settings));
+            if providers_changed {
+                let config = model.settings.current.providers.clone();
+                orders.perform_cmd(async move {
+                    Msg::ProvidersLoaded(
+                        providers::from_config(&config)
+                            .await
+                            .map_err(|e| e.to_string()),
+                    )
+                });
+            }
+        }
+        Msg::ProvidersLoaded(Ok(providers)) => {
+            model.providers = providers;
+        }
+        Msg::ProvidersLoaded(Err(e)) => {
+            error!("failed to set the providers up: {}", e);
+        }
+        Msg::ToggleHistory => {
+            model.show_history = !model.show_history;
+        }
+        Msg::History(history::Msg::PlayAgain(url)) => {
+            model.song_url = url;
+            orders.send_msg(Msg::SubmitUrl);
+        }
+        Msg::History(msg) => {
+            history::update(msg, &mut model.history, &mut orders.proxy(Msg::History));
+        }
+        Msg::TogglePlaylists => {
+            model.show_playlists = !model.show_playlists;
+        }
+        Msg::Playlists(playlists::Msg::Play { playlist, index }) => {
+            if let Some(track) = model.playlists.track(&playlist, index) {
+                model.song_url = track.url.clone();
+                update(Msg::SubmitUrl, model, orders);
+                model.playing_from = Some((playlist, index));
+            }
+        }
+        Msg::Playlists(msg) => {
+            playlists::update(msg, &mut model.playlists, &mut orders.proxy(Msg::Playlists));
+        }
+        Msg::AddToPlaylist(playlist) => {
+            if let Some(song) = model.currently_playing.as_ref() {
+                let duration = model
+                    .audio_ref
+                    .get()
+                    .map(|audio| audio.duration())
+                    .filter(|duration| duration.is_finite());
+                let track = playlists::Track::from_song(song, duration);
+                orders.send_msg(Msg::Playlists(playlists::Msg::Add { playlist, track }));
+            }
+        }
+        Msg::Download => {
+            model.download_error = None;
+            let stream = model
+                .currently_playing
+                .as_ref()
+                .zip(model.selected_quality.as_ref())
+                .and_then(|(song, quality)| {
+                    song.urls()[quality].first().map(|(_, mime_type, _)| {
+                        (metadata(song), quality.clone(), mime_type.clone())
+                    })
+                });
+            if let Some((metadata, quality, mime_type)) = stream {
+                orders.perform_cmd(async move {
+                    let request = Request::Download {
+                        quality,
+                        mime_type,
+                        metadata,
+                    };
+                    match host::call(request).await {
+                        Ok(Response::Error { message }) => Some(Msg::DownloadError(message)),
+                        Ok(_) => None,
+                        Err(e) => Some(Msg::DownloadError(e.to_string())),
+                    }
+                });
+            }
+        }
+        Msg::DownloadError(message) => {
+            error!("download failed: {}", message);
+            model.download_error = Some(message);
+        }
+        Msg::DownloadsListed(downloads, parallelism) => {
+            model.downloads = downloads
+                .into_iter()
+                .map(|download| (download.id, download))
+                .collect();
+            model.parallelism = parallelism;
+        }
+        Msg::ParallelismChanged(parallelism) => {
+            orders.skip();
+            if let Ok(parallelism) = parallelism.parse::<usize>() {
+                model.parallelism = parallelism.max(1);
+                orders.send_msg(Msg::SendToHost(Request::SetDownloadParallelism {
+                    parallelism: model.parallelism,
+                }));
+            }
+        }
+        Msg::SendToHost(request) => {
+            orders.skip();
+            if let Err(e) = host::send(request) {
+                error!("{}", e);
+            }
+        }
+        Msg::SongFailed(message) => {
+            model.refresh = None;
+            error!("failed to load song: {}", message);
+            if model.embed {
+                embed::post(&embed::Event::Error { message });
+            }
+        }
+        Msg::StreamFailed => {
+            orders.skip();
+            let song = match model.currently_playing.as_ref() {
+                Some(song) if model.refresh.is_none() => song,
+                _ => return,
+            };
+            let now = js_sys::Date::now() / 1000.0;
+            if model
+                .refreshed_at
+                .map_or(false, |refreshed_at| now - refreshed_at < REFRESH_COOLDOWN)
+            {
+                orders.send_msg(Msg::SongFailed("the stream stopped working".to_owned()));
+                return;
+            }
+            debug!(
+                "stream failed, resolving {} again (expired: {})",
+                song.source_url(),
+                song.expires_within(now, 0.0)
+            );
+            model.refresh = Some(model.audio_ref.get().map_or(
+                Refresh {
+                    position: 0.0,
+                    playing: true,
+                },
+                |audio| Refresh {
+                    position: audio.current_time(),
+                    playing: !audio.paused(),
+                },
+            ));
+            let song_url = song.source_url().clone();
+            match model.providers.iter().find(|p| p.is_match(&song_url)) {
+                Some(provider) => {
+                    let provider = Rc::clone(provider);
+                    orders.perform_cmd(async move {
+                        match provider.song_from_url(&song_url).await {
+                            Ok(song) => Msg::SongRefreshed(song),
+                            Err(e) => Msg::SongFailed(e.to_string()),
+                        }
+                    });
+                }
+                None => {
+                    model.refresh = None;
+                }
+            }
+        }
+        Msg::SongRefreshed(song) => {
+            let refresh = match model.refresh.take() {
+                Some(refresh) => refresh,
+                // Another song was loaded in the meantime.
+                None => return,
+            };
+            model.refreshed_at = Some(js_sys::Date::now() / 1000.0);
+            let quality = model
+                .selected_quality
+                .clone()
+                .filter(|quality| song.urls().contains_key(quality))
+                .or_else(|| model.settings.current.quality_policy.pick(&song));
+            model.currently_playing = Some(song);
+            model.requested_start_time = Some(refresh.position);
+            model.play_after_load = Some(refresh.playing);
+            // Rendering once without the audio element makes the next one load the new streams from scratch.
+            model.should_render_audio = false;
+            model.should_render_sources = false;
+            if let Some(quality) = quality {
+                orders.after_next_render(move |_| Msg::QualityChanged(quality));
+            }
+        }
+        Msg::TogglePlayback => {
+            orders.skip();
+            if let Some(audio) = model.audio_ref.get() {
+                if audio.paused() {
+                    // Autoplay policies can reject this, the failure shows up as a missing `play` event.
+                    let _ = audio.play();
+                } else {
+                    let _ = audio.pause();
+                }
+            }
+        }
+        Msg::Seek(time) => {
+            orders.skip();
+            if let Some(audio) = model.audio_ref.get() {
+                audio.set_current_time(time);
+            }
+            report_playback(model);
+        }
+        Msg::Media(event) => {
+            // Paused songs can outlive their streams, which are better replaced before they fail.
+            if let (MediaEvent::Play, Some(song)) = (&event, model.currently_playing.as_ref()) {
+                if song.expires_within(js_sys::Date::now() / 1000.0, EXPIRY_MARGIN) {
+                    orders.send_msg(Msg::StreamFailed);
+                }
+            }
+            match event {
+                MediaEvent::Play | MediaEvent::Pause => {
+                    if let Some(audio) = model.audio_ref.get() {
+                        media_session::set_playing(!audio.paused());
+                        media_session::set_position(audio.current_time(), audio.duration());
+                    }
+                    report_playback(model);
+                }
+                MediaEvent::Ended => {
+                    report_playback(model);
+                    if model.playing_from.is_some() {
+                        orders.send_msg(Msg::Next);
+                    }
+                }
+                MediaEvent::TimeUpdate => {}
+            }
+            // Only the embedded player's controls show playback state.
+            if !model.embed {
+                orders.skip();
+                return;
+            }
+            if let Some(audio) = model.audio_ref.get() {
+                model.playback = Playback::read(&audio);
+            }
+            let Playback {
+                current_time,
+                duration,
+                ..
+            } = model.playback;
+            embed::post(&match event {
+                MediaEvent::Play => embed::Event::Play,
+                MediaEvent::Pause => embed::Event::Pause,
+                MediaEvent::TimeUpdate => embed::Event::TimeUpdate {
+                    current_time,
+                    duration,
+                },
+                MediaEvent::Ended => embed::Event::Ended,
+            });
+        }
+        Msg::EmbedCommand(command) => match command {
+            embed::Command::Play => {
+                orders.skip();
+                if let Some(audio) = model.audio_ref.get() {
+                    let _ = audio.play();
+                }
+            }
+            embed::Command::Pause => {
+                orders.skip();
+                if let Some(audio) = model.audio_ref.get() {
+                    let _ = audio.pause();
+                }
+            }
+            embed::Command::Load { url } => {
+                model.song_url = url;
+                orders.send_msg(Msg::SubmitUrl);
+            }
+            embed::Command::Seek { time } => {
+                orders.send_msg(Msg::Seek(time));
+            }
+        },
+        Msg::MediaSession(action) => match action {
+            media_session::Action::Play => {
+                orders.skip();
+                if let Some(audio) = model.audio_ref.get() {
+                    let _ = audio.play();
+                }
+            }
+            media_session::Action::Pause => {
+                orders.skip();
+                if let Some(audio) = model.audio_ref.get() {
+                    let _ = audio.pause();
+                }
+            }
+            media_session::Action::SeekTo(time) => {
+                orders.send_msg(Msg::Seek(time));
+            }
+            media_session::Action::SeekBy(offset) => {
+                orders.skip();
+                if let Some(audio) = model.audio_ref.get() {
+                    orders.send_msg(Msg::Seek((audio.current_time() + offset).max(0.0)));
+                }
+            }
+            media_session::Action::Next => {
+                orders.send_msg(Msg::Next);
+            }
+            media_session::Action::Previous => {
+                orders.send_msg(Msg::Previous);
+            }
+        },
+        Msg::Next | Msg::Previous => {
+            let forward = matches!(msg, Msg::Next);
+            match model.playing_from.clone() {
+                Some((playlist, index)) => {
+                    let index = if forward {
+                        index.checked_add(1)
+                    } else {
+                        index.checked_sub(1)
+                    };
+                    if let Some(index) = index {
+                        update(
+                            Msg::Playlists(playlists::Msg::Play { playlist, index }),
+                            model,
+                            orders,
+                        );
+                    }
+                }
+                // Songs played some other way are linked from the page's history, see `Route`.
+                None if !model.embed => {
+                    if let Ok(history) = seed::window().history() {
+                        let _ = if forward {
+                            history.forward()
+                        } else {
+                            history.back()
+                        };
+                    }
+                }
+                None => {}
+            }
+        }
+        Msg::WindowRestored(geometry) => {
+            orders.skip();
+            window::restore(&geometry);
+            model.window = Some(geometry);
+        }
+        Msg::WindowChanged => {
+            orders.skip();
+            model.window_changes = model.window_changes.wrapping_add(1);
+            let changes = model.window_changes;
+            orders.perform_cmd(cmds::timeout(WINDOW_SAVE_DELAY, move || {
+                Msg::WindowSettled(changes)
+            }));
+        }
+        Msg::WindowSettled(changes) => {
+            orders.skip();
+            if changes == model.window_changes {
+                orders.send_msg(Msg::SaveWindow);
+            }
+        }
+        Msg::SaveWindow => {
+            orders.skip();
+            // Saving before the old geometry is restored would overwrite it with the default one.
+            if let Some(previous) = model.window.as_ref() {
+                let geometry = window::geometry(previous);
+                if geometry != *previous {
+                    model.window = Some(geometry.clone());
+                    if let Err(e) = host::send(Request::SaveWindow { geometry }) {
+                        error!("{}", e);
+                    }
+                }
+            }
+        }
+        Msg::HostEvent(event) => match event {
+            Event::DownloadUpdated { download } => {
+                model.downloads.insert(download.id, download);
+            }
+            Event::DownloadRemoved { id } => {
+                model.downloads.remove(&id);
+            }
+            Event::Control { control } => {
+                orders.skip();
+                let msg = match control {
+                    Control::Play => Msg::MediaSession(media_session::Action::Play),
+                    Control::Pause | Control::Stop => {
+                        Msg::MediaSession(media_session::Action::Pause)
+                    }
+                    Control::PlayPause => Msg::TogglePlayback,
+                    Control::Next => Msg::Next,
+                    Control::Previous => Msg::Previous,
+                    Control::Seek { offset } => {
+                        Msg::MediaSession(media_session::Action::SeekBy(offset))
+                    }
+                    Control::SetPosition { position } => Msg::Seek(position),
+                    Control::Open { url } => {
+                        model.song_url = url;
+                        Msg::SubmitUrl
+                    }
+                };
+                orders.send_msg(msg);
+            }
+        },
+    }
+}
+
+fn metadata(song: &Song) -> Metadata {
+    Metadata {
+        title: song.title().clone(),
+        artist: song.artist().clone(),
+        source_url: song.source_url().clone(),
+        artwork_url: song.artwork_url().clone(),
+    }
+}
+
+/// Tells the host what's playing, so it can show it outside the window.
+fn report_playback(model: &Model) {
+    if !model.host_available || model.embed {
+        return;
+    }
+    let audio = model.audio_ref.get();
+    let status = match (&model.currently_playing, &audio) {
+        (None, _) => PlaybackStatus::Stopped,
+        (Some(_), Some(audio)) if !audio.paused() => PlaybackStatus::Playing,
+        (Some(_), _) => PlaybackStatus::Paused,
+    };
+    let (position, duration) = audio.map_or((0.0, 0.0), |audio| {
+        let duration = audio.duration();
+        (
+            audio.current_time(),
+            if duration.is_finite() { duration } else { 0.0 },
+        )
+    });
+    let request = Request::ReportPlayback {
+        song: model.currently_playing.as_ref().map(metadata),
+        status,
+        position,
+        duration,
+    };
+    if let Err(e) = host::send(request) {
+        error!("{}", e);
+    }
+}
+
+/// Resolves the song in `model.song_url`.
+fn load_song(model: &mut Model, orders: &mut impl Orders<Msg>) {
+    // let model = model.clone();
+    model.should_render_audio = false;
+    model.refresh = None;
+    model.refreshed_at = None;
+    let song_url = model.song_url.clone();
+    let providers = model.providers.clone();
+    match providers.into_iter().find(|p| p.is_match(&song_url)) {
+        Some(p) => {
+            orders.perform_cmd(async move {
+                match p.song_from_url(&song_url).await {
+                    Ok(song) => Msg::NewSong(song),
+                    Err(e) => Msg::SongFailed(e.to_string()),
+                }
+            });
+        }
+        None => {
+            orders.send_msg(Msg::SongFailed(format!(
+                "no provider supports {}",
+                song_url
+            )));
+        }
     }
 }
 
@@ -131,6 +914,9 @@ pub fn update(msg: Msg, model: &mut Model, orders: &mut impl Orders<Msg>) {
 // ------ ------
 
 pub fn view(model: &Model) -> Node<Msg> {
+    if model.embed {
+        return main![id!["app"], embed::view(model)];
+    }
     main![
         id!["app"],
         div![
@@ -146,61 +932,212 @@ pub fn view(model: &Model) -> Node<Msg> {
                         At::Value => "";
                         At::Label => "Select quality";
                     }],
-                    model.currently_playing.as_ref().map(|song| {
-                        song.urls
-                            .keys()
-                            .map(|quality| {
-                                option![attrs! {
-                                    At::Value => quality;
-                                    At::Label => quality;
-                                }]
-                            })
-                            .collect::<Vec<_>>()
-                    }),
+                    model.currently_playing.as_ref().map(view_quality_options),
                     input_ev(Ev::Change, Msg::QualityChanged)
                 ],
+                IF!(model.host_available =>
+                    button![
+                        "Download",
+                        attrs! {
+                            At::Disabled => model.selected_quality.is_none().as_at_value();
+                        },
+                        ev(Ev::Click, |_| Msg::Download),
+                    ]
+                ),
                 input![
                     attrs! {
                         At::Placeholder => "Song URL";
+                        At::Value => model.song_url;
                     },
                     keyboard_ev(Ev::KeyDown, |keyboard_event| {
                         (keyboard_event.key_code() == ENTER_KEY).then(|| Msg::SubmitUrl)
                     }),
                     input_ev(Ev::Input, Msg::UrlChanged),
                 ],
-                button!["Load song", ev(Ev::Click, |_| Msg::SubmitUrl),]
+                button!["Load song", ev(Ev::Click, |_| Msg::SubmitUrl),],
+                button!["History", ev(Ev::Click, |_| Msg::ToggleHistory)],
+                button!["Playlists", ev(Ev::Click, |_| Msg::TogglePlaylists)],
+                button!["Settings", ev(Ev::Click, |_| Msg::ToggleSettings)],
             ],
+            IF!(model.show_settings => settings::view(&model.settings).map_msg(Msg::Settings)),
+            IF!(model.show_history => history::view(&model.history).map_msg(Msg::History)),
+            IF!(model.show_playlists => playlists::view(&model.playlists).map_msg(Msg::Playlists)),
+            model
+                .download_error
+                .as_ref()
+                .map(|message| p![C!["error"], format!("Download failed: {}", message)]),
+            IF!(!model.downloads.is_empty() => view_downloads(model)),
             model.currently_playing.as_ref().map(|song| {
                 div![
                     br![],
                     h3![id!["song-title"], song.title()],
+                    IF!(!model.playlists.playlists.is_empty() => view_add_to_playlist(model)),
                     br![],
-                    IF!(model.should_render_audio =>
-                        audio![
-                            el_ref(&model.audio_ref),
-                            id!["player"],
+                    IF!(model.should_render_audio => view_audio(model))
+                ]
+            })
+        ]
+    ]
+}
+
+/// The song's qualities, best first, labelled like "Opus 160 kbps".
+fn view_quality_options(song: &Song) -> Vec<Node<Msg>> {
+    let mut qualities = song
+        .urls()
+        .keys()
+        .map(|key| (key, song.qualities().get(key)))
+        .collect::<Vec<_>>();
+    qualities.sort_by(|(a_key, a), (b_key, b)| {
+        let bitrate = |quality: &Option<&Quality>| quality.and_then(|quality| quality.bitrate);
+        bitrate(b).cmp(&bitrate(a)).then(a_key.cmp(b_key))
+    });
+    qualities
+        .into_iter()
+        .map(|(key, quality)| {
+            option![attrs! {
+                At::Value => key;
+                At::Label => quality.map_or_else(|| key.clone(), Quality::label);
+            }]
+        })
+        .collect()
+}
+
+pub fn view_audio(model: &Model) -> Node<Msg> {
+    audio![
+        el_ref(&model.audio_ref),
+        id!["player"],
+        // Embeds draw their own controls, and wait for the parent or the user to start playing.
+        IF!(!model.embed => attrs! {
+            At::Controls => "controls";
+            At::AutoPlay => "autoplay";
+        }),
+        attrs! {
+            At::Preload => "auto";
+        },
+        ev(Ev::LoadedMetaData, |_| Msg::MetadataLoaded),
+        ev(Ev::Play, |_| Msg::Media(MediaEvent::Play)),
+        ev(Ev::Pause, |_| Msg::Media(MediaEvent::Pause)),
+        ev(Ev::TimeUpdate, |_| Msg::Media(MediaEvent::TimeUpdate)),
+        ev(Ev::Ended, |_| Msg::Media(MediaEvent::Ended)),
+        // Errors without a `MediaError`, like the sources being swapped out for another quality,
+        // aren't the stream's fault.
+        ev(Ev::Error, |event| {
+            let audio = event.target()?.unchecked_into::<HtmlMediaElement>();
+            audio.error().map(|_| Msg::StreamFailed)
+        }),
+        model.selected_quality.as_ref().map(|quality| {
+            model.currently_playing.as_ref().map(|song| {
+                let streams = &song.urls()[quality];
+                streams
+                    .iter()
+                    .enumerate()
+                    .map(|(index, (_is_hls, mime_type, url))| {
+                        source![
                             attrs! {
-                                At::Controls => "controls";
-                                At::AutoPlay => "autoplay";
-                                At::Preload => "auto";
+                                At::Src => url,
+                                At::Type => mime_type,
                             },
-                            model.selected_quality.as_ref().map(|quality| {
-                                model.currently_playing.as_ref().map(|song| {
-                                    song.urls[quality].iter().map(|(_is_hls, mime_type, url)| {
-                                        source![
-                                            attrs! {
-                                                At::Src => url,
-                                                At::Type => mime_type,
-                                            }
-                                        ]
-                                    })
-                                    .collect::<Vec<_>>()
-                                })
-                            })
+                            // Sources that fail to load report it on themselves, not on the audio element,
+                            // and the stream has only failed once the last one has.
+                            IF!(index == streams.len() - 1 => ev(Ev::Error, |_| Msg::StreamFailed)),
                         ]
-                    )
-                ]
+                    })
+                    .collect::<Vec<_>>()
             })
-        ]
+        })
+    ]
+}
+
+fn view_add_to_playlist(model: &Model) -> Node<Msg> {
+    select![
+        // Picking a playlist adds the song right away, so the select always shows its placeholder.
+        option![
+            attrs! {
+                At::Value => "";
+                At::Selected => "selected";
+            },
+            "Add to playlist…"
+        ],
+        model
+            .playlists
+            .playlists
+            .iter()
+            .map(|playlist| option![attrs! { At::Value => playlist.id }, &playlist.name]),
+        ev(Ev::Change, |event| {
+            let select = event.target()?.unchecked_into::<HtmlSelectElement>();
+            let playlist = select.value();
+            select.set_selected_index(0);
+            (!playlist.is_empty()).then(|| Msg::AddToPlaylist(playlist))
+        }),
+    ]
+}
+
+fn view_downloads(model: &Model) -> Node<Msg> {
+    section![
+        C!["downloads"],
+        label![
+            "Simultaneous downloads ",
+            input![
+                attrs! {
+                    At::Type => "number";
+                    At::Min => 1;
+                    At::Value => model.parallelism;
+                },
+                input_ev(Ev::Change, Msg::ParallelismChanged),
+            ],
+        ],
+        ul![model.downloads.values().map(view_download)],
+    ]
+}
+
+fn view_download(download: &Download) -> Node<Msg> {
+    let id = download.id;
+    let control = move |label: &str, request: fn(u32) -> Request| {
+        button![label, ev(Ev::Click, move |_| Msg::SendToHost(request(id)))]
+    };
+    li![
+        span![&download.title],
+        match &download.state {
+            DownloadState::Queued => span!["Queued"],
+            DownloadState::Running => match download.total {
+                Some(total) => progress![attrs! {
+                    At::Value => download.downloaded;
+                    At::Max => total;
+                }],
+                // Without a known size the bar is indeterminate.
+                None => progress![],
+            },
+            DownloadState::Paused => span!["Paused"],
+            DownloadState::Retrying {
+                attempt,
+                delay_secs,
+            } => span![format!(
+                "Attempt {} failed, retrying in {}s",
+                attempt, delay_secs
+            )],
+            DownloadState::Finished { path } => span![format!("Saved to {}", path)],
+            DownloadState::Failed { message } => {
+                span![C!["error"], format!("Failed: {}", message)]
+            }
+            DownloadState::Cancelled => span!["Cancelled"],
+        },
+        div![match &download.state {
+            DownloadState::Queued | DownloadState::Running | DownloadState::Retrying { .. } =>
+                vec![
+                    control("Pause", |id| Request::PauseDownload { id }),
+                    control("Cancel", |id| Request::CancelDownload { id }),
+                ],
+            DownloadState::Paused => vec![
+                control("Resume", |id| Request::ResumeDownload { id }),
+                control("Cancel", |id| Request::CancelDownload { id }),
+            ],
+            DownloadState::Failed { .. } | DownloadState::Cancelled => vec![
+                control("Retry", |id| Request::ResumeDownload { id }),
+                control("Remove", |id| Request::RemoveDownload { id }),
+            ],
+            DownloadState::Finished { .. } => {
+                vec![control("Remove", |id| Request::RemoveDownload { id })]
+            }
+        }],
     ]
 }
@@ -0,0 +1,360 @@
+//! Reading and writing playlists as JSON, extended M3U and XSPF.
+
+use super::{Playlist, Track};
+use simple_eyre::eyre::{eyre, Result};
+use web_sys::{DomParser, Element, SupportedType};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Format {
+    Json,
+    M3u8,
+    Xspf,
+}
+
+impl Format {
+    pub const ALL: [Format; 3] = [Format::Json, Format::M3u8, Format::Xspf];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Format::Json => "JSON",
+            Format::M3u8 => "M3U8",
+            Format::Xspf => "XSPF",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Json => "json",
+            Format::M3u8 => "m3u8",
+            Format::Xspf => "xspf",
+        }
+    }
+
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            Format::Json => "application/json",
+            Format::M3u8 => "audio/x-mpegurl",
+            Format::Xspf => "application/xspf+xml",
+        }
+    }
+
+    /// Guesses the format from a file's extension, `.m3u` files are read as UTF-8 too.
+    pub fn from_file_name(name: &str) -> Option<Self> {
+        let extension = name.rsplit('.').next()?.to_lowercase();
+        match extension.as_str() {
+            "json" => Some(Format::Json),
+            "m3u" | "m3u8" => Some(Format::M3u8),
+            "xspf" => Some(Format::Xspf),
+            _ => None,
+        }
+    }
+
+    pub fn write(self, playlist: &Playlist) -> Result<String> {
+        Ok(match self {
+            Format::Json => serde_json::to_string_pretty(playlist)?,
+            Format::M3u8 => write_m3u8(playlist),
+            Format::Xspf => write_xspf(playlist),
+        })
+    }
+
+    /// Reads a playlist, named `fallback_name` if the file doesn't have a name of its own.
+    ///
+    /// The playlist still needs an id before it's saved.
+    pub fn read(self, text: &str, fallback_name: &str) -> Result<Playlist> {
+        let mut playlist = match self {
+            Format::Json => serde_json::from_str(text)?,
+            Format::M3u8 => read_m3u8(text),
+            Format::Xspf => read_xspf(text)?,
+        };
+        if playlist.name.is_empty() {
+            playlist.name = fallback_name.to_owned();
+        }
+        Ok(playlist)
+    }
+}
+
+/// The `Artist - Title` form players show for `#EXTINF` entries.
+fn display_title(track: &Track) -> String {
+    match &track.artist {
+        Some(artist) => format!("{} - {}", artist, track.title),
+        None => track.title.clone(),
+    }
+}
+
+/// Joins the lines of `text` with spaces, since every line of an M3U file is an entry or a tag of its own.
+fn single_line(text: &str) -> String {
+    text.split(&['\r', '\n'][..])
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn write_m3u8(playlist: &Playlist) -> String {
+    let mut m3u8 = format!("#EXTM3U\n#PLAYLIST:{}\n", single_line(&playlist.name));
+    for track in &playlist.tracks {
+        // -1 is what the format uses for an unknown length.
+        let duration = track
+            .duration
+            .map_or(-1, |duration| duration.round() as i64);
+        m3u8 += &format!(
+            "#EXTINF:{},{}\n{}\n",
+            duration,
+            single_line(&display_title(track)),
+            single_line(&track.url)
+        );
+    }
+    m3u8
+}
+
+fn read_m3u8(text: &str) -> Playlist {
+    let mut playlist = Playlist::default();
+    // The `#EXTINF` line describing the next url.
+    let mut info: Option<(Option<f64>, String)> = None;
+    for line in text.lines().map(str::trim) {
+        if let Some(name) = line.strip_prefix("#PLAYLIST:") {
+            playlist.name = name.trim().to_owned();
+        } else if let Some(extinf) = line.strip_prefix("#EXTINF:") {
+            let mut parts = extinf.splitn(2, ',');
+            let duration = parts
+                .next()
+                .and_then(|duration| duration.trim().parse::<f64>().ok())
+                .filter(|duration| *duration >= 0.0);
+            info = Some((duration, parts.next().unwrap_or("").trim().to_owned()));
+        } else if !line.is_empty() && !line.starts_with('#') {
+            let (duration, title) = info.take().unwrap_or_default();
+            let (artist, title) = match title.find(" - ") {
+                Some(index) => (
+                    Some(title[..index].to_owned()),
+                    title[index + 3..].to_owned(),
+                ),
+                None if title.is_empty() => (None, line.to_owned()),
+                None => (None, title),
+            };
+            playlist.tracks.push(Track {
+                url: line.to_owned(),
+                title,
+                artist,
+                artwork_url: None,
+                duration,
+            });
+        }
+    }
+    playlist
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn write_xspf(playlist: &Playlist) -> String {
+    let mut xspf = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\">\n  \
+         <title>{}</title>\n  <trackList>\n",
+        escape_xml(&playlist.name)
+    );
+    for track in &playlist.tracks {
+        xspf += "    <track>\n";
+        xspf += &format!(
+            "      <location>{}</location>\n      <title>{}</title>\n",
+            escape_xml(&track.url),
+            escape_xml(&track.title)
+        );
+        if let Some(artist) = &track.artist {
+            xspf += &format!("      <creator>{}</creator>\n", escape_xml(artist));
+        }
+        if let Some(artwork_url) = &track.artwork_url {
+            xspf += &format!("      <image>{}</image>\n", escape_xml(artwork_url));
+        }
+        if let Some(duration) = track.duration {
+            // XSPF durations are in milliseconds.
+            xspf += &format!(
+                "      <duration>{}</duration>\n",
+                (duration * 1000.0) as u64
+            );
+        }
+        xspf += "    </track>\n";
+    }
+    xspf += "  </trackList>\n</playlist>\n";
+    xspf
+}
+
+/// The text of `element`'s first child called `name`.
+fn child_text(element: &Element, name: &str) -> Option<String> {
+    element
+        .get_elements_by_tag_name(name)
+        .item(0)?
+        .text_content()
+        .map(|text| text.trim().to_owned())
+        .filter(|text| !text.is_empty())
+}
+
+fn read_xspf(text: &str) -> Result<Playlist> {
+    let document = DomParser::new()
+        .and_then(|parser| parser.parse_from_string(text, SupportedType::TextXml))
+        .map_err(|e| eyre!("failed to parse XSPF: {:?}", e))?;
+    let root = document
+        .document_element()
+        .filter(|root| root.tag_name() == "playlist")
+        .ok_or_else(|| eyre!("not an XSPF playlist"))?;
+
+    let tracks = root.get_elements_by_tag_name("track");
+    let tracks = (0..tracks.length())
+        .filter_map(|index| tracks.item(index))
+        .filter_map(|track| {
+            let url = child_text(&track, "location")?;
+            Some(Track {
+                title: child_text(&track, "title").unwrap_or_else(|| url.clone()),
+                artist: child_text(&track, "creator"),
+                artwork_url: child_text(&track, "image"),
+                duration: child_text(&track, "duration")
+                    .and_then(|duration| duration.parse::<f64>().ok())
+                    .map(|duration| duration / 1000.0),
+                url,
+            })
+        })
+        .collect();
+    // Only the playlist's own title, not the ones of its tracks.
+    let children = root.children();
+    let name = (0..children.length())
+        .filter_map(|index| children.item(index))
+        .find(|child| child.tag_name() == "title")
+        .and_then(|title| title.text_content())
+        .unwrap_or_default();
+    Ok(Playlist {
+        name: name.trim().to_owned(),
+        tracks,
+        ..Playlist::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(url: &str, title: &str, artist: Option<&str>, duration: Option<f64>) -> Track {
+        Track {
+            url: url.to_owned(),
+            title: title.to_owned(),
+            artist: artist.map(str::to_owned),
+            artwork_url: None,
+            duration,
+        }
+    }
+
+    /// What an M3U8 file keeps of a playlist: its name and each track's url, title, artist and duration.
+    type Summary = (String, Vec<(String, String, Option<String>, Option<f64>)>);
+
+    fn summary(playlist: &Playlist) -> Summary {
+        let tracks = playlist
+            .tracks
+            .iter()
+            .map(|track| {
+                (
+                    track.url.clone(),
+                    track.title.clone(),
+                    track.artist.clone(),
+                    track.duration,
+                )
+            })
+            .collect();
+        (playlist.name.clone(), tracks)
+    }
+
+    fn playlist() -> Playlist {
+        Playlist {
+            name: "Road trip".to_owned(),
+            tracks: vec![
+                track("https://soundcloud.com/a/b", "B", Some("A"), Some(185.0)),
+                track("https://www.youtube.com/watch?v=c", "C", None, None),
+            ],
+            ..Playlist::default()
+        }
+    }
+
+    #[test]
+    fn writes_m3u8() {
+        assert_eq!(
+            write_m3u8(&playlist()),
+            "#EXTM3U\n#PLAYLIST:Road trip\n\
+             #EXTINF:185,A - B\nhttps://soundcloud.com/a/b\n\
+             #EXTINF:-1,C\nhttps://www.youtube.com/watch?v=c\n"
+        );
+    }
+
+    #[test]
+    fn keeps_m3u8_tags_on_one_line() {
+        let mut playlist = playlist();
+        playlist.name = "Road\r\ntrip\n".to_owned();
+        playlist.tracks[0].title = "B\n#EXTINF:1,injected".to_owned();
+        let m3u8 = write_m3u8(&playlist);
+        assert!(m3u8.contains("#PLAYLIST:Road trip\n"));
+        assert!(m3u8.contains("#EXTINF:185,A - B #EXTINF:1,injected\n"));
+        assert_eq!(read_m3u8(&m3u8).tracks.len(), 2);
+    }
+
+    #[test]
+    fn reads_m3u8() {
+        let playlist = read_m3u8(
+            "#EXTM3U\r\n#PLAYLIST: Mix \r\n\r\n\
+             #EXTINF:-1,A - B - C\r\nhttps://example.com/1\r\n\
+             #EXTINF:12.5,\r\nhttps://example.com/2\r\n\
+             # a comment\r\nhttps://example.com/3\r\n",
+        );
+        assert_eq!(
+            summary(&playlist),
+            (
+                "Mix".to_owned(),
+                vec![
+                    (
+                        "https://example.com/1".to_owned(),
+                        "B - C".to_owned(),
+                        Some("A".to_owned()),
+                        None
+                    ),
+                    (
+                        "https://example.com/2".to_owned(),
+                        "https://example.com/2".to_owned(),
+                        None,
+                        Some(12.5)
+                    ),
+                    (
+                        "https://example.com/3".to_owned(),
+                        "https://example.com/3".to_owned(),
+                        None,
+                        None
+                    ),
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn round_trips_m3u8() {
+        let playlist = playlist();
+        assert_eq!(
+            summary(&read_m3u8(&write_m3u8(&playlist))),
+            summary(&playlist)
+        );
+
+        let empty = Playlist {
+            name: "Empty".to_owned(),
+            ..Playlist::default()
+        };
+        assert_eq!(summary(&read_m3u8(&write_m3u8(&empty))), summary(&empty));
+    }
+
+    #[test]
+    fn escapes_xml() {
+        assert_eq!(escape_xml("plain"), "plain");
+        assert_eq!(
+            escape_xml("<a href=\"?x=1&y=2\">"),
+            "&lt;a href=&quot;?x=1&amp;y=2&quot;&gt;"
+        );
+        assert_eq!(escape_xml("&amp;"), "&amp;amp;");
+    }
+}
@@ -0,0 +1,380 @@
+//! Named lists of songs, kept in IndexedDB.
+//!
+//! Tracks store the page a song was resolved from and enough metadata to show it, never stream urls,
+//! since those expire.
+
+mod format;
+
+pub use format::Format;
+
+use crate::file;
+use crate::idb::Database;
+use crate::providers::Song;
+use seed::{prelude::*, *};
+use serde::{Deserialize, Serialize};
+use simple_eyre::eyre::{eyre, Result};
+use web_sys::File;
+
+const STORE: &str = "playlists";
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Track {
+    pub url: String,
+    pub title: String,
+    pub artist: Option<String>,
+    pub artwork_url: Option<String>,
+    /// In seconds, known once the song has been played.
+    pub duration: Option<f64>,
+}
+
+impl Track {
+    pub fn from_song(song: &Song, duration: Option<f64>) -> Self {
+        Self {
+            url: song.source_url().clone(),
+            title: song.title().clone(),
+            artist: song.artist().clone(),
+            artwork_url: song.artwork_url().clone(),
+            duration,
+        }
+    }
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Playlist {
+    /// Imported playlists get a new one, so importing the same file twice doesn't replace anything.
+    #[serde(default)]
+    pub id: String,
+    pub name: String,
+    pub tracks: Vec<Track>,
+}
+
+fn new_id() -> String {
+    format!(
+        "{:x}-{:x}",
+        js_sys::Date::now() as u64,
+        (js_sys::Math::random() * f64::from(u32::MAX)) as u32
+    )
+}
+
+async fn all() -> Result<Vec<Playlist>> {
+    Database::open().await?.get_all(STORE).await
+}
+
+async fn put(playlist: Playlist) -> Result<()> {
+    Database::open().await?.put(STORE, &playlist).await
+}
+
+async fn delete(id: String) -> Result<()> {
+    Database::open().await?.delete(STORE, &id).await
+}
+
+async fn import(file: File) -> Result<Playlist> {
+    let name = file.name();
+    let format =
+        Format::from_file_name(&name).ok_or_else(|| eyre!("{} isn't a known playlist", name))?;
+    let stem = name.rsplitn(2, '.').last().unwrap_or(&name);
+    let mut playlist = format.read(&file::read(&file).await?, stem)?;
+    playlist.id = new_id();
+    Ok(playlist)
+}
+
+fn saved(result: Result<()>) -> Msg {
+    Msg::Saved(result.map_err(|e| e.to_string()))
+}
+
+pub async fn load() -> Msg {
+    Msg::Loaded(all().await.map_err(|e| e.to_string()))
+}
+
+// ------ ------
+//     Model
+// ------ ------
+
+#[derive(Clone, Default)]
+pub struct Model {
+    /// Sorted by name.
+    pub playlists: Vec<Playlist>,
+    /// The playlist whose tracks are shown.
+    pub open: Option<String>,
+    pub new_name: String,
+    pub error: Option<String>,
+}
+
+impl Model {
+    pub fn track(&self, playlist: &str, index: usize) -> Option<&Track> {
+        self.playlists
+            .iter()
+            .find(|p| p.id == playlist)?
+            .tracks
+            .get(index)
+    }
+
+    fn get_mut(&mut self, id: &str) -> Option<&mut Playlist> {
+        self.playlists.iter_mut().find(|playlist| playlist.id == id)
+    }
+
+    fn insert(&mut self, playlist: Playlist) {
+        self.playlists.push(playlist);
+        self.playlists
+            .sort_by(|a, b| a.name.to_lowercase().cmp(&b.name.to_lowercase()));
+    }
+}
+
+// ------ ------
+//    Update
+// ------ ------
+
+pub enum Msg {
+    Loaded(Result<Vec<Playlist>, String>),
+    NewNameChanged(String),
+    Create,
+    Delete(String),
+    Toggle(String),
+    Add {
+        playlist: String,
+        track: Track,
+    },
+    Move {
+        playlist: String,
+        from: usize,
+        to: usize,
+    },
+    Remove {
+        playlist: String,
+        index: usize,
+    },
+    /// Handled by the parent, which loads the song and keeps track of where it came from.
+    Play {
+        playlist: String,
+        index: usize,
+    },
+    Export {
+        playlist: String,
+        format: Format,
+    },
+    Import(File),
+    Imported(Result<Playlist, String>),
+    Saved(Result<(), String>),
+}
+
+pub fn update(msg: Msg, model: &mut Model, orders: &mut impl Orders<Msg>) {
+    // The id of a playlist that changed and has to be saved.
+    let changed = match msg {
+        Msg::Loaded(Ok(playlists)) => {
+            for playlist in playlists {
+                model.insert(playlist);
+            }
+            None
+        }
+        Msg::Loaded(Err(e)) | Msg::Imported(Err(e)) | Msg::Saved(Err(e)) => {
+            model.error = Some(e);
+            None
+        }
+        Msg::Saved(Ok(())) => {
+            orders.skip();
+            None
+        }
+        Msg::NewNameChanged(name) => {
+            model.new_name = name;
+            None
+        }
+        Msg::Create => {
+            let name = model.new_name.trim().to_owned();
+            if name.is_empty() {
+                return;
+            }
+            model.new_name.clear();
+            let id = new_id();
+            model.insert(Playlist {
+                id: id.clone(),
+                name,
+                tracks: Vec::new(),
+            });
+            Some(id)
+        }
+        Msg::Delete(id) => {
+            model.playlists.retain(|playlist| playlist.id != id);
+            orders.perform_cmd(async move { saved(delete(id).await) });
+            None
+        }
+        Msg::Toggle(id) => {
+            model.open = if model.open.as_ref() == Some(&id) {
+                None
+            } else {
+                Some(id)
+            };
+            None
+        }
+        Msg::Add { playlist, track } => model.get_mut(&playlist).map(|playlist| {
+            playlist.tracks.push(track);
+            playlist.id.clone()
+        }),
+        Msg::Move { playlist, from, to } => model
+            .get_mut(&playlist)
+            .filter(|playlist| from < playlist.tracks.len() && to < playlist.tracks.len())
+            .map(|playlist| {
+                let track = playlist.tracks.remove(from);
+                playlist.tracks.insert(to, track);
+                playlist.id.clone()
+            }),
+        Msg::Remove { playlist, index } => model
+            .get_mut(&playlist)
+            .filter(|playlist| index < playlist.tracks.len())
+            .map(|playlist| {
+                playlist.tracks.remove(index);
+                playlist.id.clone()
+            }),
+        Msg::Play { .. } => None,
+        Msg::Export { playlist, format } => {
+            orders.skip();
+            if let Some(playlist) = model.playlists.iter().find(|p| p.id == playlist) {
+                let name = format!("{}.{}", playlist.name, format.extension());
+                let saved = format
+                    .write(playlist)
+                    .and_then(|text| file::save(&name, format.mime_type(), &text));
+                if let Err(e) = saved {
+                    model.error = Some(e.to_string());
+                    orders.render();
+                }
+            }
+            None
+        }
+        Msg::Import(file) => {
+            orders.perform_cmd(async move {
+                Msg::Imported(import(file).await.map_err(|e| e.to_string()))
+            });
+            None
+        }
+        Msg::Imported(Ok(playlist)) => {
+            let id = playlist.id.clone();
+            model.insert(playlist);
+            model.open = Some(id.clone());
+            Some(id)
+        }
+    };
+
+    let changed = changed.and_then(|id| model.playlists.iter().find(|p| p.id == id).cloned());
+    if let Some(playlist) = changed {
+        model.error = None;
+        orders.perform_cmd(async move { saved(put(playlist).await) });
+    }
+}
+
+// ------ ------
+//     View
+// ------ ------
+
+pub fn view(model: &Model) -> Node<Msg> {
+    section![
+        C!["playlists"],
+        h3!["Playlists"],
+        div![
+            C!["row"],
+            input![
+                attrs! {
+                    At::Placeholder => "New playlist";
+                    At::Value => model.new_name;
+                },
+                input_ev(Ev::Input, Msg::NewNameChanged),
+            ],
+            button!["Create", ev(Ev::Click, |_| Msg::Create)],
+            label![
+                C!["import"],
+                "Import",
+                input![
+                    attrs! {
+                        At::Type => "file";
+                        At::Accept => ".json,.m3u,.m3u8,.xspf";
+                    },
+                    ev(Ev::Change, |event| file::picked(event).map(Msg::Import)),
+                ],
+            ],
+        ],
+        model.error.as_ref().map(|error| p![C!["error"], error]),
+        ul![model
+            .playlists
+            .iter()
+            .map(|playlist| view_playlist(playlist, model.open.as_ref() == Some(&playlist.id)))],
+    ]
+}
+
+fn view_playlist(playlist: &Playlist, open: bool) -> Node<Msg> {
+    let id = playlist.id.clone();
+    li![
+        div![
+            C!["row"],
+            button![
+                C!["playlist-name"],
+                format!("{} ({})", playlist.name, playlist.tracks.len()),
+                ev(Ev::Click, {
+                    let id = id.clone();
+                    move |_| Msg::Toggle(id)
+                }),
+            ],
+            Format::ALL.iter().map(|&format| {
+                let id = id.clone();
+                button![
+                    format.label(),
+                    attrs! { At::Title => format!("Export as {}", format.label()) },
+                    ev(Ev::Click, move |_| Msg::Export {
+                        playlist: id,
+                        format
+                    }),
+                ]
+            }),
+            button![
+                "Delete",
+                ev(Ev::Click, {
+                    let id = id.clone();
+                    move |_| Msg::Delete(id)
+                })
+            ],
+        ],
+        IF!(open => ol![playlist
+            .tracks
+            .iter()
+            .enumerate()
+            .map(|(index, track)| view_track(&id, index, playlist.tracks.len(), track))]),
+    ]
+}
+
+fn view_track(playlist: &str, index: usize, count: usize, track: &Track) -> Node<Msg> {
+    let action = |label: &str, disabled: bool, msg: fn(String, usize) -> Msg| {
+        let playlist = playlist.to_owned();
+        button![
+            label,
+            attrs! { At::Disabled => disabled.as_at_value() },
+            ev(Ev::Click, move |_| msg(playlist, index)),
+        ]
+    };
+    li![
+        span![
+            C!["playlist-track"],
+            &track.title,
+            track
+                .artist
+                .as_ref()
+                .map(|artist| format!(" by {}", artist)),
+        ],
+        action("Play", false, |playlist, index| Msg::Play {
+            playlist,
+            index
+        }),
+        action("↑", index == 0, |playlist, index| Msg::Move {
+            playlist,
+            from: index,
+            to: index.saturating_sub(1)
+        }),
+        action("↓", index + 1 == count, |playlist, index| Msg::Move {
+            playlist,
+            from: index,
+            to: index + 1
+        }),
+        action("Remove", false, |playlist, index| Msg::Remove {
+            playlist,
+            index
+        }),
+    ]
+}
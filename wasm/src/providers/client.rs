@@ -0,0 +1,41 @@
+use super::proxy;
+use crate::host;
+use bridge::{Request, Response};
+use frick_providers::{async_trait, web::FetchClient, HttpClient};
+use log::*;
+use simple_eyre::eyre::{eyre, Result};
+
+/// Fetches cross-origin urls through the configured CORS proxy,
+/// or the native host's bridge when the desktop app didn't inject its local proxy.
+///
+/// The local proxy is preferred over the bridge, since it doesn't need to pass the whole body through `eval`.
+pub struct AppClient {
+    fetch: FetchClient,
+}
+
+impl AppClient {
+    pub fn new() -> Self {
+        Self {
+            fetch: FetchClient::new(|url| proxy::wrap(&proxy::current(), url)),
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl HttpClient for AppClient {
+    async fn get_text(&self, url: &str, cross_origin: bool) -> Result<String> {
+        if cross_origin && host::is_available() && !proxy::is_injected() {
+            trace!("fetching {} through the native host", url);
+            return match host::call(Request::Fetch {
+                url: url.to_owned(),
+            })
+            .await?
+            {
+                Response::Text { body } => Ok(body),
+                Response::Error { message } => Err(eyre!("failed to fetch {}: {}", url, message)),
+                response => Err(eyre!("unexpected response to a fetch: {:?}", response)),
+            };
+        }
+        self.fetch.get_text(url, cross_origin).await
+    }
+}
@@ -1,13 +1,20 @@
-mod common;
-#[cfg(feature = "soundcloud")]
-mod soundcloud;
-#[cfg(feature = "youtube")]
-mod youtube;
-
-pub use common::{Provider, Song};
-#[cfg(feature = "soundcloud")]
-pub use soundcloud::SoundCloud;
-#[cfg(feature = "youtube")]
-pub use youtube::YouTube;
-
-pub use async_trait::async_trait;
+mod client;
+pub mod proxy;
+
+pub use client::AppClient;
+pub use frick_providers::{names, Config, Provider, Quality, QualityPolicy, Song};
+
+use crate::settings;
+use frick_providers::web::WebStorage;
+use simple_eyre::eyre::Result;
+use std::rc::Rc;
+
+/// Sets up every provider enabled at compile time that `config` doesn't turn off.
+pub async fn from_config(config: &Config) -> Result<Vec<Rc<dyn Provider>>> {
+    frick_providers::all(Rc::new(AppClient::new()), Rc::new(WebStorage), config).await
+}
+
+/// Sets up the providers turned on in the saved settings.
+pub async fn all() -> Result<Vec<Rc<dyn Provider>>> {
+    from_config(&settings::load().await.providers).await
+}
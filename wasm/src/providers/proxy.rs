@@ -0,0 +1,78 @@
+//! Fetching through a CORS proxy, picked from the page's url, the desktop app or the settings.
+
+use crate::settings;
+use bridge::{ProxyConfig, ProxyStyle};
+use seed::{document, prelude::*};
+use simple_eyre::eyre::{eyre, Result};
+
+/// The proxy to use, in order of precedence:
+/// a `?proxy=` query parameter, the one injected by the desktop app, the saved one and the default.
+pub fn current() -> ProxyConfig {
+    from_query()
+        .or_else(injected)
+        .or_else(|| settings::stored().map(|settings| settings.proxy))
+        .unwrap_or_default()
+}
+
+/// `?proxy=<url>`, optionally with `&proxy_style=query`.
+fn from_query() -> Option<ProxyConfig> {
+    let url = Url::current();
+    let search = url.search();
+    let proxy_url = search.get("proxy")?.first()?.to_owned();
+    let style = search
+        .get("proxy_style")
+        .and_then(|styles| styles.first())
+        .and_then(|style| ProxyStyle::from_key(style))
+        .unwrap_or(ProxyStyle::PathPrefix);
+    Some(ProxyConfig {
+        url: proxy_url,
+        style,
+    })
+}
+
+/// The desktop app advertises its loopback proxy through a `frick-proxy` meta tag.
+fn injected() -> Option<ProxyConfig> {
+    document()
+        .query_selector(r#"meta[name="frick-proxy"]"#)
+        .ok()
+        .flatten()
+        .and_then(|meta| meta.get_attribute("content"))
+        .map(|url| ProxyConfig {
+            url,
+            style: ProxyStyle::PathPrefix,
+        })
+}
+
+/// Whether the current proxy is the desktop app's own one, which can't be overridden from settings.
+pub fn is_injected() -> bool {
+    injected().is_some()
+}
+
+/// Rewrites `url` so it's fetched through `proxy`.
+pub fn wrap(proxy: &ProxyConfig, url: &str) -> String {
+    match proxy.style {
+        ProxyStyle::PathPrefix => format!("{}/{}", proxy.url.trim_end_matches('/'), url),
+        ProxyStyle::Query => format!(
+            "{}{}url={}",
+            proxy.url,
+            if proxy.url.contains('?') { '&' } else { '?' },
+            String::from(js_sys::encode_uri_component(url))
+        ),
+    }
+}
+
+/// Fetches a page through `proxy` to check that it's reachable and behaves.
+pub async fn test(proxy: &ProxyConfig) -> Result<()> {
+    let url = wrap(proxy, "https://soundcloud.com");
+    fetch(&url)
+        .await
+        .map_err(|_| eyre!("couldn't reach {}", proxy.url))?
+        .check_status()
+        .map_err(|e| match e {
+            FetchError::StatusError(status) => {
+                eyre!("proxy answered {} {}", status.code, status.text)
+            }
+            _ => eyre!("proxy returned an invalid response"),
+        })?;
+    Ok(())
+}
@@ -0,0 +1,57 @@
+//! The page url's query, which links straight to a song: `?url=<song>&quality=<quality>&t=<seconds>`.
+
+use frick_providers::parse_timestamp;
+use seed::prelude::*;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Route {
+    pub url: Option<String>,
+    pub quality: Option<String>,
+    /// Overrides the start offset in the song's own url.
+    pub start_time: Option<f64>,
+}
+
+impl Route {
+    pub fn from_url(url: &Url) -> Self {
+        let param = |key: &str| {
+            url.search()
+                .get(key)
+                .and_then(|values| values.first())
+                .filter(|value| !value.is_empty())
+                .cloned()
+        };
+        Self {
+            url: param("url"),
+            quality: param("quality"),
+            start_time: param("t").and_then(|t| parse_timestamp(&t)),
+        }
+    }
+
+    /// Adds a history entry, which back and forward navigation returns to.
+    pub fn push(&self) {
+        self.to_url().go_and_push();
+    }
+
+    /// Updates the address bar without adding a history entry.
+    pub fn replace(&self) {
+        self.to_url().go_and_replace();
+    }
+
+    /// The current page url with this route in it, keeping other parameters like `proxy` as they are.
+    fn to_url(&self) -> Url {
+        let mut url = Url::current();
+        let search = url.search_mut();
+        let mut set = |key: &str, value: Option<String>| match value {
+            Some(value) => {
+                search.insert(key.to_owned(), vec![value]);
+            }
+            None => {
+                search.remove(key);
+            }
+        };
+        set("url", self.url.clone());
+        set("quality", self.quality.clone());
+        set("t", self.start_time.map(|t| t.to_string()));
+        url
+    }
+}
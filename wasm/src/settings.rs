@@ -0,0 +1,313 @@
+//! The settings page, editing the settings the desktop app keeps in its config file,
+//! or LocalStorage when there's no desktop app.
+
+use crate::host;
+use crate::providers::{self, proxy, QualityPolicy};
+use bridge::{ProxyConfig, ProxyStyle, Request, Response, Settings};
+use log::error;
+use seed::{prelude::*, *};
+use simple_eyre::eyre::{eyre, Result};
+
+const STORAGE_KEY: &str = "settings";
+/// Where settings were kept before they were shared with the desktop app, read once to migrate them.
+const LEGACY_PROXY_KEY: &str = "cors_proxy";
+const LEGACY_QUALITY_POLICY_KEY: &str = "quality_policy";
+const LEGACY_NOTIFICATIONS_KEY: &str = "notifications";
+
+/// The choices for [`QualityPolicy`] as `(key, label)`, the codec one takes its codec from a separate field.
+const QUALITY_POLICIES: [(&str, &str); 4] = [
+    ("highest_bitrate", "Highest bitrate"),
+    ("prefer_opus", "Prefer Opus"),
+    ("data_saver", "Data saver"),
+    ("codec", "A specific codec"),
+];
+
+fn policy_key(policy: &QualityPolicy) -> &'static str {
+    match policy {
+        QualityPolicy::HighestBitrate => "highest_bitrate",
+        QualityPolicy::PreferOpus => "prefer_opus",
+        QualityPolicy::DataSaver => "data_saver",
+        QualityPolicy::Codec(_) => "codec",
+    }
+}
+
+/// The settings kept in LocalStorage, brought up to date.
+pub fn stored() -> Option<Settings> {
+    LocalStorage::get::<_, Settings>(STORAGE_KEY)
+        .ok()
+        .map(Settings::migrate)
+}
+
+/// Settings from the keys used before there was a settings model, which are all optional.
+fn legacy() -> Settings {
+    let defaults = Settings::default();
+    Settings {
+        proxy: LocalStorage::get(LEGACY_PROXY_KEY).unwrap_or(defaults.proxy),
+        quality_policy: LocalStorage::get(LEGACY_QUALITY_POLICY_KEY)
+            .unwrap_or(defaults.quality_policy),
+        notifications: LocalStorage::get(LEGACY_NOTIFICATIONS_KEY)
+            .unwrap_or(defaults.notifications),
+        ..defaults
+    }
+}
+
+/// The desktop app's settings, or the ones in LocalStorage when there's no desktop app.
+pub async fn load() -> Settings {
+    if host::is_available() {
+        match host::call(Request::GetSettings).await {
+            Ok(Response::Settings { settings }) => return settings,
+            Ok(response) => error!("unexpected response to getting settings: {:?}", response),
+            Err(e) => error!("failed to get settings: {}", e),
+        }
+    }
+    stored().unwrap_or_else(legacy)
+}
+
+async fn save(settings: Settings) -> Result<()> {
+    if !host::is_available() {
+        return LocalStorage::insert(STORAGE_KEY, &settings)
+            .map_err(|_| eyre!("failed to store settings"));
+    }
+    match host::call(Request::SaveSettings { settings }).await? {
+        Response::Saved => Ok(()),
+        Response::Error { message } => Err(eyre!(message)),
+        response => Err(eyre!(
+            "unexpected response to saving settings: {:?}",
+            response
+        )),
+    }
+}
+
+fn saved(result: Result<()>) -> Msg {
+    Msg::Saved(result.map_err(|e| e.to_string()))
+}
+
+// ------ ------
+//     Model
+// ------ ------
+
+#[derive(Clone)]
+pub struct Model {
+    /// As last saved, everything but the proxy is saved as soon as it's changed.
+    pub current: Settings,
+    /// Saved once it's been tested, or the user says so.
+    pub proxy: ProxyConfig,
+    pub proxy_injected: bool,
+    pub status: Option<String>,
+    /// Kept around while another policy is picked.
+    pub codec: String,
+    host_available: bool,
+}
+
+impl Model {
+    pub fn new(settings: Settings) -> Self {
+        Self {
+            proxy: settings.proxy.clone(),
+            proxy_injected: proxy::is_injected(),
+            status: None,
+            codec: match &settings.quality_policy {
+                QualityPolicy::Codec(codec) => codec.clone(),
+                _ => "opus".to_owned(),
+            },
+            host_available: host::is_available(),
+            current: settings,
+        }
+    }
+
+    fn save(&mut self, orders: &mut impl Orders<Msg>) {
+        let settings = self.current.clone();
+        orders.perform_cmd(async move { saved(save(settings).await) });
+    }
+}
+
+// ------ ------
+//    Update
+// ------ ------
+
+pub enum Msg {
+    ProxyUrlChanged(String),
+    ProxyStyleChanged(String),
+    Save,
+    Test,
+    TestFinished(Result<(), String>),
+    QualityPolicyChanged(String),
+    CodecChanged(String),
+    ToggleNotifications,
+    /// The parent sets the providers up again after these two.
+    InvidiousInstanceChanged(String),
+    ToggleProvider(&'static str),
+    Saved(Result<(), String>),
+}
+
+pub fn update(msg: Msg, model: &mut Model, orders: &mut impl Orders<Msg>) {
+    match msg {
+        Msg::ProxyUrlChanged(url) => {
+            model.proxy.url = url;
+            model.status = None;
+        }
+        Msg::ProxyStyleChanged(style) => {
+            if let Some(style) = ProxyStyle::from_key(&style) {
+                model.proxy.style = style;
+                model.status = None;
+            }
+        }
+        Msg::Save => {
+            model.current.proxy = model.proxy.clone();
+            model.save(orders);
+        }
+        Msg::Test => {
+            model.status = Some("Testing…".to_owned());
+            let proxy = model.proxy.clone();
+            orders.perform_cmd(async move {
+                Msg::TestFinished(proxy::test(&proxy).await.map_err(|e| e.to_string()))
+            });
+        }
+        Msg::TestFinished(result) => {
+            model.status = Some(match result {
+                Ok(()) => "The proxy works".to_owned(),
+                Err(e) => format!("The proxy doesn't work: {}", e),
+            });
+        }
+        Msg::QualityPolicyChanged(key) => {
+            model.current.quality_policy = match key.as_str() {
+                "highest_bitrate" => QualityPolicy::HighestBitrate,
+                "prefer_opus" => QualityPolicy::PreferOpus,
+                "data_saver" => QualityPolicy::DataSaver,
+                "codec" => QualityPolicy::Codec(model.codec.clone()),
+                _ => return,
+            };
+            model.save(orders);
+        }
+        Msg::CodecChanged(codec) => {
+            model.codec = codec.trim().to_lowercase();
+            if let QualityPolicy::Codec(_) = model.current.quality_policy {
+                model.current.quality_policy = QualityPolicy::Codec(model.codec.clone());
+                model.save(orders);
+            }
+        }
+        Msg::ToggleNotifications => {
+            model.current.notifications = !model.current.notifications;
+            model.save(orders);
+        }
+        Msg::InvidiousInstanceChanged(instance) => {
+            model.current.providers.invidious_instance = instance.trim().to_owned();
+            model.save(orders);
+        }
+        Msg::ToggleProvider(name) => {
+            let enabled = model.current.providers.is_enabled(name);
+            model.current.providers.set_enabled(name, !enabled);
+            model.save(orders);
+        }
+        Msg::Saved(result) => {
+            model.status = Some(match result {
+                Ok(()) => "Saved".to_owned(),
+                Err(e) => format!("Failed to save the settings: {}", e),
+            });
+        }
+    }
+}
+
+// ------ ------
+//     View
+// ------ ------
+
+pub fn view(model: &Model) -> Node<Msg> {
+    section![
+        C!["settings"],
+        h3!["Settings"],
+        IF!(model.proxy_injected =>
+            p!["The desktop app uses its own proxy, these settings only apply in the browser."]
+        ),
+        label![
+            "CORS proxy",
+            input![
+                attrs! {
+                    At::Value => model.proxy.url;
+                    At::Placeholder => "https://warp-co.rs";
+                },
+                input_ev(Ev::Input, Msg::ProxyUrlChanged),
+            ],
+        ],
+        label![
+            "Proxy style",
+            select![
+                ProxyStyle::ALL.iter().map(|style| {
+                    option![
+                        attrs! {
+                            At::Value => style.key();
+                            At::Selected => (model.proxy.style == *style).as_at_value();
+                        },
+                        style.label()
+                    ]
+                }),
+                input_ev(Ev::Change, Msg::ProxyStyleChanged),
+            ],
+        ],
+        div![
+            C!["row"],
+            button!["Test", ev(Ev::Click, |_| Msg::Test)],
+            button!["Save", ev(Ev::Click, |_| Msg::Save)],
+        ],
+        label![
+            "Quality",
+            select![
+                QUALITY_POLICIES.iter().map(|(key, label)| {
+                    option![
+                        attrs! {
+                            At::Value => key;
+                            At::Selected => (policy_key(&model.current.quality_policy) == *key).as_at_value();
+                        },
+                        label
+                    ]
+                }),
+                input_ev(Ev::Change, Msg::QualityPolicyChanged),
+            ],
+        ],
+        IF!(matches!(model.current.quality_policy, QualityPolicy::Codec(_)) => label![
+            "Codec",
+            input![
+                attrs! {
+                    At::Value => model.codec;
+                    At::Placeholder => "opus, aac, mp3…";
+                },
+                input_ev(Ev::Change, Msg::CodecChanged),
+            ],
+        ]),
+        fieldset![
+            legend!["Providers"],
+            providers::names().into_iter().map(|name| {
+                label![
+                    input![
+                        attrs! {
+                            At::Type => "checkbox";
+                            At::Checked => model.current.providers.is_enabled(name).as_at_value();
+                        },
+                        ev(Ev::Change, move |_| Msg::ToggleProvider(name)),
+                    ],
+                    name,
+                ]
+            }),
+        ],
+        label![
+            "Invidious instance",
+            input![
+                attrs! {
+                    At::Value => model.current.providers.invidious_instance;
+                    At::Placeholder => frick_providers::DEFAULT_INVIDIOUS_INSTANCE;
+                },
+                input_ev(Ev::Change, Msg::InvidiousInstanceChanged),
+            ],
+        ],
+        IF!(model.host_available => label![
+            input![
+                attrs! {
+                    At::Type => "checkbox";
+                    At::Checked => model.current.notifications.as_at_value();
+                },
+                ev(Ev::Change, |_| Msg::ToggleNotifications),
+            ],
+            "Notify about new songs",
+        ]),
+        model.status.as_ref().map(|status| p![status]),
+    ]
+}
@@ -0,0 +1,58 @@
+//! The desktop app's window, whose geometry the host saves but can only partly restore.
+//!
+//! web-view sizes the window itself, placing and maximizing it is left to the page.
+
+use bridge::WindowGeometry;
+use seed::{prelude::JsValue, window};
+
+fn pixels(value: Result<JsValue, JsValue>) -> Option<i32> {
+    value.ok()?.as_f64().map(|pixels| pixels as i32)
+}
+
+/// Guessed from the window covering the whole screen, since pages aren't told.
+fn is_maximized() -> bool {
+    let window = window();
+    let screen = match window.screen() {
+        Ok(screen) => screen,
+        Err(_) => return false,
+    };
+    let covers = |outer, available: Result<i32, JsValue>| match (outer, available) {
+        (Some(outer), Ok(available)) => outer >= available,
+        _ => false,
+    };
+    covers(pixels(window.outer_width()), screen.avail_width())
+        && covers(pixels(window.outer_height()), screen.avail_height())
+}
+
+/// Where the window is now, keeping `previous`'s size and position while it's maximized.
+pub fn geometry(previous: &WindowGeometry) -> WindowGeometry {
+    if is_maximized() {
+        return WindowGeometry {
+            maximized: true,
+            ..previous.clone()
+        };
+    }
+    let window = window();
+    WindowGeometry {
+        width: pixels(window.inner_width()).unwrap_or(previous.width),
+        height: pixels(window.inner_height()).unwrap_or(previous.height),
+        x: window.screen_x().ok().or(previous.x),
+        y: window.screen_y().ok().or(previous.y),
+        maximized: false,
+    }
+}
+
+/// Moves the window back to where `geometry` says, engines that don't let pages move windows ignore it.
+pub fn restore(geometry: &WindowGeometry) {
+    let window = window();
+    if geometry.maximized {
+        if let Ok(screen) = window.screen() {
+            let _ = window.move_to(0, 0);
+            if let (Ok(width), Ok(height)) = (screen.avail_width(), screen.avail_height()) {
+                let _ = window.resize_to(width, height);
+            }
+        }
+    } else if let (Some(x), Some(y)) = (geometry.x, geometry.y) {
+        let _ = window.move_to(x, y);
+    }
+}